@@ -1,37 +1,107 @@
 use std::{
     collections::{HashMap, VecDeque},
+    fmt,
     sync::Arc,
+    time::{Duration, Instant},
 };
 
 use anyhow::Context;
 use dashmap::DashMap;
 use itertools::Itertools;
 use multimap::MultiMap;
-use regex::{Regex, RegexSet};
+use rand::{seq::SliceRandom, Rng};
+use regex::{RegexSet, RegexSetBuilder};
 use serde::{Deserialize, Serialize};
 use teloxide::types::{ChatId, Sticker};
 use tokio::sync::RwLock;
 
-use crate::{chat_config::ChatConfigModel, db::DatabaseRef};
+use crate::{autoreply_pattern::AutoreplyPattern, chat_config::ChatConfigModel, db::DatabaseRef};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum AutoreplyResponse {
     Literal(String),
     Sticker(String),
+    /// Text expanded with `autoreply_template::expand_template` before sending.
+    Template(String),
+    // Media responses are stored by Telegram file_id.
+    Photo(String),
+    Animation(String),
+    Voice(String),
+    VideoNote(String),
+    Document(String),
+}
+
+impl fmt::Display for AutoreplyResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AutoreplyResponse::Literal(text) => write!(f, "{}", text),
+            AutoreplyResponse::Sticker(_) => write!(f, "[tarra]"),
+            AutoreplyResponse::Template(template) => write!(f, "{} (pohja)", template),
+            AutoreplyResponse::Photo(_) => write!(f, "[kuva]"),
+            AutoreplyResponse::Animation(_) => write!(f, "[GIF]"),
+            AutoreplyResponse::Voice(_) => write!(f, "[ääniviesti]"),
+            AutoreplyResponse::VideoNote(_) => write!(f, "[videoviesti]"),
+            AutoreplyResponse::Document(_) => write!(f, "[tiedosto]"),
+        }
+    }
+}
+
+pub const DEFAULT_RESPONSE_WEIGHT: u32 = 1;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WeightedResponse {
+    pub response: AutoreplyResponse,
+    pub weight: u32,
 }
 
 #[derive(Clone, Debug)]
 pub struct Autoreply {
     pub chat_id: ChatId,
     pub name: String,
-    pub pattern_regex: Regex,
-    pub response: AutoreplyResponse,
+    pub pattern: AutoreplyPattern,
+    /// One of these is picked at random each time the autoreply fires.
+    pub responses: Vec<WeightedResponse>,
+    /// Overrides the chat's autoreply chance for this autoreply.
+    pub chance: Option<f64>,
+    /// Minimum time between two triggers of this autoreply.
+    pub cooldown: Option<Duration>,
+    pub enabled: bool,
+}
+
+impl Autoreply {
+    pub fn new(
+        chat_id: ChatId,
+        name: String,
+        pattern: AutoreplyPattern,
+        response: AutoreplyResponse,
+    ) -> Self {
+        Self {
+            chat_id,
+            name,
+            pattern,
+            responses: vec![WeightedResponse {
+                response,
+                weight: DEFAULT_RESPONSE_WEIGHT,
+            }],
+            chance: None,
+            cooldown: None,
+            enabled: true,
+        }
+    }
+
+    pub fn choose_response(&self, rng: &mut impl Rng) -> Option<&AutoreplyResponse> {
+        self.responses
+            .choose_weighted(rng, |response| response.weight)
+            .ok()
+            .map(|response| &response.response)
+    }
 }
 
 #[derive(Debug)]
 pub struct AutoreplySet {
     autoreplies_by_regex: MultiMap<String, Autoreply>,
     autoreply_set: RegexSet,
+    last_triggered: DashMap<String, Instant>,
 }
 
 impl AutoreplySet {
@@ -39,6 +109,7 @@ impl AutoreplySet {
         Self {
             autoreplies_by_regex: MultiMap::new(),
             autoreply_set: RegexSet::empty(),
+            last_triggered: DashMap::new(),
         }
     }
 
@@ -47,26 +118,80 @@ impl AutoreplySet {
 
         for autoreply in autoreplies {
             autoreplies_by_regex.insert(
-                autoreply.pattern_regex.as_str().to_string(),
+                autoreply.pattern.regex.as_str().to_string(),
                 autoreply.clone(),
             );
         }
 
-        let autoreply_set = RegexSet::new(
-            autoreplies
-                .iter()
-                .map(|autoreply| autoreply.pattern_regex.as_str())
-                .unique(),
-        )
-        .expect("Creating regex set should never fail");
-
-        Self {
-            autoreplies_by_regex,
-            autoreply_set,
+        match build_regex_set(autoreplies_by_regex.keys()) {
+            Ok(autoreply_set) => Self {
+                autoreplies_by_regex,
+                autoreply_set,
+                last_triggered: DashMap::new(),
+            },
+            Err(err) => {
+                log::error!(
+                    "Failed to create regex set, adding autoreplies one by one: {}",
+                    err
+                );
+
+                let mut autoreply_set = Self::empty();
+
+                for autoreply in autoreplies {
+                    if let Err(err) = autoreply_set.add_autoreply(autoreply.clone()) {
+                        log::error!("Skipping autoreply {}: {}", autoreply.name, err);
+                    }
+                }
+
+                autoreply_set
+            }
         }
     }
 
-    pub fn get_matches<'a>(&'a self, message: &str) -> Vec<&'a Autoreply> {
+    /// Returns the autoreplies that should fire for the message, honoring each autoreply's
+    /// enabled flag, cooldown and chance (falling back to `default_chance`).
+    /// Replies to the bot always fire regardless of the chance.
+    ///
+    /// The cooldowns only start once `mark_triggered` is called for the replies actually sent.
+    pub fn get_matches<'a>(
+        &'a self,
+        message: &str,
+        default_chance: f64,
+        is_reply_to_me: bool,
+    ) -> Vec<&'a Autoreply> {
+        let now = Instant::now();
+
+        self.find_matches(message)
+            .into_iter()
+            .filter(|autoreply| autoreply.enabled)
+            .filter(|autoreply| {
+                match (autoreply.cooldown, self.last_triggered.get(&autoreply.name)) {
+                    (Some(cooldown), Some(last_triggered)) => {
+                        now.saturating_duration_since(*last_triggered) >= cooldown
+                    }
+                    _ => true,
+                }
+            })
+            .filter(|autoreply| {
+                if is_reply_to_me {
+                    return true;
+                }
+
+                let p: f64 = rand::random();
+                p < autoreply.chance.unwrap_or(default_chance)
+            })
+            .collect()
+    }
+
+    /// Starts the cooldown of an autoreply that was sent.
+    pub fn mark_triggered(&self, autoreply: &Autoreply) {
+        self.last_triggered
+            .insert(autoreply.name.clone(), Instant::now());
+    }
+
+    /// Returns all autoreplies whose pattern matches the message, ignoring their chance,
+    /// cooldown and enabled state.
+    pub fn find_matches<'a>(&'a self, message: &str) -> Vec<&'a Autoreply> {
         let match_collection = self.autoreply_set.matches(message);
         let mut matching_autoreplies = Vec::new();
 
@@ -81,17 +206,58 @@ impl AutoreplySet {
         matching_autoreplies
     }
 
-    pub fn add_autoreply(&mut self, autoreply: Autoreply) {
+    pub fn get_autoreply(&self, name: &str) -> Option<&Autoreply> {
+        self.iter().find(|autoreply| autoreply.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Autoreply> {
         self.autoreplies_by_regex
-            .insert(autoreply.pattern_regex.as_str().to_string(), autoreply);
+            .iter_all()
+            .flat_map(|(_, autoreplies)| autoreplies)
+    }
+
+    /// Adds an autoreply, replacing any existing autoreply with the same name.
+    /// The set is left unchanged if the new pattern cannot be compiled into the regex set.
+    pub fn add_autoreply(&mut self, autoreply: Autoreply) -> Result<(), regex::Error> {
+        let mut autoreplies_by_regex = self.autoreplies_by_regex.clone();
+
+        autoreplies_by_regex.retain(|_, existing| existing.name != autoreply.name);
+        autoreplies_by_regex.insert(autoreply.pattern.regex.as_str().to_string(), autoreply);
 
-        self.autoreply_set = RegexSet::new(
-            self.autoreplies_by_regex
-                .iter()
-                .map(|(_, autoreply)| autoreply.pattern_regex.as_str()),
-        )
-        .expect("Creating regex set should never fail");
+        self.autoreply_set = build_regex_set(autoreplies_by_regex.keys())?;
+        self.autoreplies_by_regex = autoreplies_by_regex;
+
+        Ok(())
     }
+
+    /// Removes the autoreply with the given name, returning it if it existed.
+    /// The set is left unchanged if the remaining patterns cannot be compiled into the regex set.
+    pub fn remove_autoreply(&mut self, name: &str) -> Result<Option<Autoreply>, regex::Error> {
+        let removed = match self.get_autoreply(name).cloned() {
+            Some(removed) => removed,
+            None => return Ok(None),
+        };
+
+        let mut autoreplies_by_regex = self.autoreplies_by_regex.clone();
+        autoreplies_by_regex.retain(|_, autoreply| autoreply.name != name);
+
+        self.autoreply_set = build_regex_set(autoreplies_by_regex.keys())?;
+        self.autoreplies_by_regex = autoreplies_by_regex;
+        self.last_triggered.remove(name);
+
+        Ok(Some(removed))
+    }
+}
+
+/// Upper limit for the compiled size of a chat's regex set, in bytes.
+const REGEX_SET_SIZE_LIMIT: usize = 32 * 1024 * 1024;
+
+fn build_regex_set<'a>(
+    patterns: impl IntoIterator<Item = &'a String>,
+) -> Result<RegexSet, regex::Error> {
+    RegexSetBuilder::new(patterns)
+        .size_limit(REGEX_SET_SIZE_LIMIT)
+        .build()
 }
 
 pub type AutoreplySetMap = Arc<RwLock<HashMap<ChatId, AutoreplySet>>>;
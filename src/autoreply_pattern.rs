@@ -0,0 +1,273 @@
+use std::{fmt, str::FromStr};
+
+use itertools::Itertools;
+use once_cell::sync::OnceCell;
+use regex::{Regex, RegexBuilder};
+use thiserror::Error;
+
+/// Upper limit for the compiled size of a single pattern, in bytes.
+pub const PATTERN_SIZE_LIMIT: usize = 256 * 1024;
+
+/// Patterns matching a larger share of the sample corpus than this are rejected.
+const MAX_SAMPLE_MATCH_RATIO: f64 = 0.25;
+
+const DEFAULT_SAMPLE_CORPUS: &str = include_str!("autoreply_sample_corpus.txt");
+
+static SAMPLE_CORPUS: OnceCell<Vec<String>> = OnceCell::new();
+
+/// Returns the sample messages used to catch overly broad patterns.
+/// A custom corpus with one message per line can be provided with `AUTOREPLY_SAMPLE_CORPUS`.
+fn get_sample_corpus() -> &'static [String] {
+    SAMPLE_CORPUS.get_or_init(|| {
+        let corpus = match std::env::var("AUTOREPLY_SAMPLE_CORPUS") {
+            Ok(path) => match std::fs::read_to_string(&path) {
+                Ok(corpus) => corpus,
+                Err(err) => {
+                    log::error!("Failed to read sample corpus {}: {}", path, err);
+                    String::from(DEFAULT_SAMPLE_CORPUS)
+                }
+            },
+            Err(_) => String::from(DEFAULT_SAMPLE_CORPUS),
+        };
+
+        corpus
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect()
+    })
+}
+
+#[derive(Debug, Error)]
+pub enum PatternError {
+    #[error("Regex-lausekkeen parsinta epäonnistui: {0}")]
+    Regex(#[from] regex::Error),
+    #[error("Kuvio osuu tyhjään viestiin, joten se vastaisi ihan kaikkeen.")]
+    MatchesEmpty,
+    #[error("Kuvio on liian laaja: se osuu {matched}/{total} esimerkkiviestiin.")]
+    TooBroad { matched: usize, total: usize },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MatchMode {
+    /// The pattern is used as a regex as is.
+    Regex,
+    /// Case-insensitive match of whole words.
+    Word,
+    /// Case-insensitive match anywhere in the message.
+    Text,
+    /// Case-insensitive whole word match that also accepts common Finnish inflections
+    /// and missing umlauts.
+    Fuzzy,
+}
+
+impl FromStr for MatchMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "regex" => Ok(MatchMode::Regex),
+            "word" => Ok(MatchMode::Word),
+            "text" => Ok(MatchMode::Text),
+            "fuzzy" => Ok(MatchMode::Fuzzy),
+            _ => Err(anyhow::anyhow!("Invalid match mode: {}", s)),
+        }
+    }
+}
+
+impl MatchMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MatchMode::Regex => "regex",
+            MatchMode::Word => "word",
+            MatchMode::Text => "text",
+            MatchMode::Fuzzy => "fuzzy",
+        }
+    }
+
+    fn to_regex_source(self, pattern: &str) -> String {
+        match self {
+            MatchMode::Regex => pattern.to_string(),
+            MatchMode::Word => format!(r"(?i)\b{}\b", regex::escape(pattern)),
+            MatchMode::Text => format!("(?i){}", regex::escape(pattern)),
+            MatchMode::Fuzzy => {
+                let words = pattern
+                    .split_whitespace()
+                    .map(|word| fuzzy_word(&word.to_lowercase()))
+                    .join(r"\s+");
+                format!(
+                    r"(?i)\b{}{}{}\b",
+                    words, FINNISH_CASE_ENDINGS, FINNISH_CLITICS
+                )
+            }
+        }
+    }
+}
+
+// Only the last word of a fuzzy pattern is inflected, e.g. "kahvi" matches "kahvia" and
+// "kahvillakin" but not "kahvinkeitin".
+const FINNISH_CASE_ENDINGS: &str = "(?:[nt]|[aä]|t[aä]|ss[aä]|st[aä]|ll[aä]|lt[aä]|lle|n[aä]|ksi|tt[aä]|in|en|ihin|iin|j[aä]|it[aä]|ien|iss[aä]|ist[aä]|ill[aä]|ilt[aä]|ille|in[aä]|iksi)?";
+const FINNISH_CLITICS: &str = "(?:kin|k[aä][aä]n|k[oö]|h[aä]n|p[aä]|s)?";
+
+fn fuzzy_word(word: &str) -> String {
+    let mut source = String::new();
+    let mut chars = word.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let is_last = chars.peek().is_none();
+
+        match c {
+            'a' | 'ä' => source.push_str("[aä]"),
+            'o' | 'ö' => source.push_str("[oö]"),
+            // A final i often turns into e when inflected, e.g. "kivi" -> "kiven"
+            'i' if is_last => source.push_str("[ie]"),
+            c => source.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    source
+}
+
+#[derive(Clone, Debug)]
+pub struct AutoreplyPattern {
+    pub match_mode: MatchMode,
+    /// The pattern as given by the user.
+    pub pattern: String,
+    /// The pattern compiled according to the match mode.
+    pub regex: Regex,
+}
+
+impl AutoreplyPattern {
+    pub fn new(match_mode: MatchMode, pattern: String) -> Result<Self, regex::Error> {
+        let regex = RegexBuilder::new(&match_mode.to_regex_source(&pattern))
+            .size_limit(PATTERN_SIZE_LIMIT)
+            .build()?;
+
+        Ok(Self {
+            match_mode,
+            pattern,
+            regex,
+        })
+    }
+
+    /// Parses a pattern with an optional match mode prefix, e.g. `word:kahvi`.
+    /// Patterns without a known prefix are treated as regexes.
+    pub fn parse(input: &str) -> Result<Self, regex::Error> {
+        let (match_mode, pattern) = match input.split_once(':') {
+            Some((prefix, pattern)) => match MatchMode::from_str(prefix) {
+                Ok(match_mode) => (match_mode, pattern),
+                Err(_) => (MatchMode::Regex, input),
+            },
+            None => (MatchMode::Regex, input),
+        };
+
+        Self::new(match_mode, pattern.to_string())
+    }
+
+    /// Checks that the pattern does not make the bot reply to (almost) every message.
+    pub fn validate(&self) -> Result<(), PatternError> {
+        if self.regex.is_match("") {
+            return Err(PatternError::MatchesEmpty);
+        }
+
+        let corpus = get_sample_corpus();
+        let total = corpus.len();
+        let matched = corpus
+            .iter()
+            .filter(|message| self.regex.is_match(message))
+            .count();
+
+        if total > 0 && matched as f64 / total as f64 > MAX_SAMPLE_MATCH_RATIO {
+            return Err(PatternError::TooBroad { matched, total });
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for AutoreplyPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.match_mode {
+            MatchMode::Regex => write!(f, "{}", self.pattern),
+            match_mode => write!(f, "{}:{}", match_mode.as_str(), self.pattern),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(input: &str, message: &str) -> bool {
+        AutoreplyPattern::parse(input)
+            .unwrap()
+            .regex
+            .is_match(message)
+    }
+
+    #[test]
+    fn parse_without_prefix_as_regex() {
+        let pattern = AutoreplyPattern::parse("(?i)ka+hvi").unwrap();
+        assert_eq!(pattern.match_mode, MatchMode::Regex);
+        assert_eq!(pattern.regex.as_str(), "(?i)ka+hvi");
+    }
+
+    #[test]
+    fn parse_unknown_prefix_as_regex() {
+        let pattern = AutoreplyPattern::parse("(?:a):b").unwrap();
+        assert_eq!(pattern.match_mode, MatchMode::Regex);
+        assert_eq!(pattern.pattern, "(?:a):b");
+    }
+
+    #[test]
+    fn reject_pattern_matching_empty_message() {
+        let pattern = AutoreplyPattern::parse(".*").unwrap();
+        assert!(matches!(
+            pattern.validate(),
+            Err(PatternError::MatchesEmpty)
+        ));
+    }
+
+    #[test]
+    fn reject_broad_pattern() {
+        let pattern = AutoreplyPattern::parse(".").unwrap();
+        assert!(matches!(
+            pattern.validate(),
+            Err(PatternError::TooBroad { .. })
+        ));
+    }
+
+    #[test]
+    fn accept_specific_pattern() {
+        let pattern = AutoreplyPattern::parse("word:kahvi").unwrap();
+        assert!(pattern.validate().is_ok());
+    }
+
+    #[test]
+    fn reject_huge_pattern() {
+        let result = AutoreplyPattern::parse(r"\w{1000}\w{1000}\w{1000}");
+        assert!(matches!(result, Err(regex::Error::CompiledTooBig(_))));
+    }
+
+    #[test]
+    fn match_word() {
+        assert!(is_match("word:kahvi", "Missä KAHVI?"));
+        assert!(!is_match("word:kahvi", "kahvinkeitin on rikki"));
+    }
+
+    #[test]
+    fn match_text() {
+        assert!(is_match("text:kahvi", "KAHVInkeitin on rikki"));
+        assert!(!is_match("text:a.b", "axb"));
+    }
+
+    #[test]
+    fn match_fuzzy() {
+        assert!(is_match("fuzzy:kahvi", "onko kahvia"));
+        assert!(is_match("fuzzy:kahvi", "Kahvillakin"));
+        assert!(is_match("fuzzy:kahvi", "kahveja"));
+        assert!(is_match("fuzzy:pöytä", "poytaa"));
+        assert!(!is_match("fuzzy:kahvi", "kahvinkeitin"));
+    }
+}
@@ -0,0 +1,191 @@
+use chrono::NaiveDate;
+use rand::{seq::SliceRandom, Rng};
+use regex::Captures;
+
+pub struct TemplateContext<'a> {
+    pub captures: Option<Captures<'a>>,
+    pub sender_name: &'a str,
+    pub date: NaiveDate,
+    /// strftime format used for `{date}`, usually from the chat's locale.
+    pub date_format: &'a str,
+}
+
+/// Expands an autoreply template.
+///
+/// Supported syntax:
+/// - `$1`, `${name}`: capture groups from the matched message (`$$` for a literal `$`)
+/// - `{sender}`: the sender's first name
+/// - `{date}`: the current date
+/// - `{a|b|c}`: a random pick between the alternatives, which can contain capture groups
+///
+/// Any other braces are kept as is.
+pub fn expand_template(template: &str, context: &TemplateContext, rng: &mut impl Rng) -> String {
+    let expanded = expand_braces(template, context, rng);
+
+    match &context.captures {
+        Some(captures) => {
+            let mut output = String::new();
+            captures.expand(&expanded, &mut output);
+            output
+        }
+        None => expanded,
+    }
+}
+
+fn expand_braces(template: &str, context: &TemplateContext, rng: &mut impl Rng) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(index) = rest.find(['$', '{']) {
+        output.push_str(&rest[..index]);
+        rest = &rest[index..];
+
+        if let Some(after_dollar) = rest.strip_prefix('$') {
+            // Capture references are left for Captures::expand, including ${name} and $$.
+            let reference_len = match after_dollar.chars().next() {
+                Some('$') => 2,
+                Some('{') => after_dollar.find('}').map(|end| end + 2).unwrap_or(1),
+                _ => 1,
+            };
+            output.push_str(&rest[..reference_len]);
+            rest = &rest[reference_len..];
+            continue;
+        }
+
+        let end = match find_group_end(rest) {
+            Some(end) => end,
+            None => break,
+        };
+
+        let inner = &rest[1..end];
+
+        match inner {
+            "sender" => push_substituted(&mut output, context.sender_name, context),
+            "date" => push_substituted(
+                &mut output,
+                &context.date.format(context.date_format).to_string(),
+                context,
+            ),
+            // The alternatives are part of the template, so capture references in them expand too.
+            inner if inner.contains('|') => {
+                let alternatives = inner.split('|').collect::<Vec<_>>();
+                output.push_str(alternatives.choose(rng).unwrap_or(&""));
+            }
+            _ => output.push_str(&rest[..=end]),
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Returns the index of the `}` closing the group at the start of `s`, skipping over
+/// capture references like `${name}` inside it.
+fn find_group_end(s: &str) -> Option<usize> {
+    let mut chars = s.char_indices().skip(1).peekable();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '}' => return Some(index),
+            '$' if matches!(chars.peek(), Some((_, '{'))) => {
+                chars.find(|(_, c)| *c == '}')?;
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Substituted text is not part of the template, so any `$` in it must stay literal
+/// when the capture references are expanded afterwards.
+fn push_substituted(output: &mut String, text: &str, context: &TemplateContext) {
+    if context.captures.is_some() {
+        output.push_str(&text.replace('$', "$$"));
+    } else {
+        output.push_str(text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{rngs::StdRng, SeedableRng};
+    use regex::Regex;
+
+    fn expand(template: &str, regex: &str, message: &str) -> String {
+        expand_with_sender(template, regex, message, "Matti")
+    }
+
+    fn expand_with_sender(template: &str, regex: &str, message: &str, sender: &str) -> String {
+        let regex = Regex::new(regex).unwrap();
+        let context = TemplateContext {
+            captures: regex.captures(message),
+            sender_name: sender,
+            date: NaiveDate::from_ymd(2022, 9, 24),
+            date_format: "%d.%m.%Y",
+        };
+        expand_template(template, &context, &mut StdRng::seed_from_u64(0))
+    }
+
+    #[test]
+    fn expand_numbered_capture() {
+        let actual = expand("no sinä itse $1", "olet (\\w+)", "olet tyhmä");
+        assert_eq!(actual, "no sinä itse tyhmä");
+    }
+
+    #[test]
+    fn expand_named_capture() {
+        let actual = expand("${thing}!!", "(?P<thing>kahvi)", "missä kahvi");
+        assert_eq!(actual, "kahvi!!");
+    }
+
+    #[test]
+    fn expand_sender_and_date() {
+        let actual = expand("moi {sender}, tänään on {date}", "moi", "moi");
+        assert_eq!(actual, "moi Matti, tänään on 24.09.2022");
+    }
+
+    #[test]
+    fn expand_alternatives() {
+        let actual = expand("{a|b|c}", "x", "x");
+        assert!(["a", "b", "c"].contains(&actual.as_str()));
+    }
+
+    #[test]
+    fn keep_unknown_and_unterminated_braces() {
+        let actual = expand("{foo} {bar", "x", "x");
+        assert_eq!(actual, "{foo} {bar");
+    }
+
+    #[test]
+    fn keep_literal_dollar() {
+        let actual = expand("maksaa $$5", "x", "x");
+        assert_eq!(actual, "maksaa $5");
+    }
+
+    #[test]
+    fn keep_dollar_in_sender_name() {
+        let actual = expand_with_sender("moi {sender}, $1", "olet (\\w+)", "olet tyhmä", "$1 $x");
+        assert_eq!(actual, "moi $1 $x, tyhmä");
+
+        let actual = expand_with_sender("moi {sender}", "moi", "moi", "$$");
+        assert_eq!(actual, "moi $$");
+    }
+
+    #[test]
+    fn expand_captures_in_alternative() {
+        let actual = expand("{no sinä itse $1|en ole $1}", "olet (\\w+)", "olet tyhmä");
+        assert!(["no sinä itse tyhmä", "en ole tyhmä"].contains(&actual.as_str()));
+
+        let actual = expand(
+            "{${thing}!!|lisää ${thing}a}",
+            "(?P<thing>kahvi)",
+            "missä kahvi",
+        );
+        assert!(["kahvi!!", "lisää kahvia"].contains(&actual.as_str()));
+    }
+}
@@ -1,19 +1,78 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Display, str::FromStr};
 
 use anyhow::Context;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
 use teloxide::types::ChatId;
+use thiserror::Error;
 use tokio::sync::RwLock;
 
-use crate::db::DatabaseRef;
+use crate::{db::DatabaseRef, quiet_hours::QuietHours};
 
 pub const DEFAULT_AUTOREPLY_CHANCE: f64 = 0.5;
 pub const DEFAULT_STICKER_LRU_SIZE: u32 = 20;
+pub const DEFAULT_REPLY_BURST: u32 = 5;
+pub const DEFAULT_REPLIES_PER_HOUR: u32 = 60;
+pub const DEFAULT_TIMEZONE: Tz = chrono_tz::Europe::Helsinki;
+
+/// Controls how dates are formatted in messages.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DateLocale {
+    Fi,
+    En,
+    Iso,
+}
+
+impl FromStr for DateLocale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fi" => Ok(DateLocale::Fi),
+            "en" => Ok(DateLocale::En),
+            "iso" => Ok(DateLocale::Iso),
+            _ => Err(anyhow::anyhow!("Invalid locale: {}", s)),
+        }
+    }
+}
+
+impl DateLocale {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DateLocale::Fi => "fi",
+            DateLocale::En => "en",
+            DateLocale::Iso => "iso",
+        }
+    }
+
+    pub fn date_format(&self) -> &'static str {
+        match self {
+            DateLocale::Fi => "%d.%m.%Y",
+            DateLocale::En => "%m/%d/%Y",
+            DateLocale::Iso => "%Y-%m-%d",
+        }
+    }
+
+    pub fn time_format(&self) -> &'static str {
+        match self {
+            DateLocale::Fi | DateLocale::Iso => "%H:%M",
+            DateLocale::En => "%I:%M %p",
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct ChatConfig {
     pub chat_id: ChatId,
     pub autoreply_chance: f64,
     pub sticker_lru_size: u32,
+    /// How many unrequested replies the bot can send in a row. 0 disables rate limiting.
+    pub reply_burst: u32,
+    /// How fast the reply burst refills.
+    pub replies_per_hour: u32,
+    pub quiet_hours: QuietHours,
+    pub timezone: Tz,
+    pub locale: DateLocale,
 }
 
 impl ChatConfig {
@@ -22,6 +81,190 @@ impl ChatConfig {
             chat_id,
             autoreply_chance: DEFAULT_AUTOREPLY_CHANCE,
             sticker_lru_size: DEFAULT_STICKER_LRU_SIZE,
+            reply_burst: DEFAULT_REPLY_BURST,
+            replies_per_hour: DEFAULT_REPLIES_PER_HOUR,
+            quiet_hours: QuietHours::default(),
+            timezone: DEFAULT_TIMEZONE,
+            locale: DateLocale::Fi,
+        }
+    }
+
+    /// The current time in the chat's timezone.
+    pub fn now(&self) -> DateTime<Tz> {
+        Utc::now().with_timezone(&self.timezone)
+    }
+
+    /// Converts a time in the chat's timezone to UTC. Times skipped by a daylight saving change
+    /// are moved an hour forward.
+    pub fn local_to_utc(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        self.timezone
+            .from_local_datetime(&local)
+            .earliest()
+            .or_else(|| {
+                self.timezone
+                    .from_local_datetime(&(local + Duration::hours(1)))
+                    .earliest()
+            })
+            .map(|date_time| date_time.with_timezone(&Utc))
+            .unwrap_or_else(|| Utc.from_utc_datetime(&local))
+    }
+
+    pub fn today(&self) -> NaiveDate {
+        self.now().date_naive()
+    }
+
+    pub fn format_date(&self, date: NaiveDate) -> String {
+        date.format(self.locale.date_format()).to_string()
+    }
+
+    pub fn format_date_time(&self, date_time: NaiveDateTime) -> String {
+        format!(
+            "{} {}",
+            self.format_date(date_time.date()),
+            date_time.format(self.locale.time_format())
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SettingError {
+    #[error("Tuntematon asetus {0}. Asetukset näet komennolla /config")]
+    UnknownKey(String),
+    #[error("Epäkelpo arvo asetukselle {key}: {expected}")]
+    InvalidValue { key: &'static str, expected: String },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChatSetting {
+    AutoreplyChance,
+    StickerLruSize,
+    ReplyBurst,
+    RepliesPerHour,
+    QuietHours,
+    Timezone,
+    Locale,
+}
+
+impl FromStr for ChatSetting {
+    type Err = SettingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ChatSetting::ALL
+            .iter()
+            .find(|setting| setting.key() == s)
+            .copied()
+            .ok_or_else(|| SettingError::UnknownKey(s.to_string()))
+    }
+}
+
+impl ChatSetting {
+    pub const ALL: &'static [ChatSetting] = &[
+        ChatSetting::AutoreplyChance,
+        ChatSetting::StickerLruSize,
+        ChatSetting::ReplyBurst,
+        ChatSetting::RepliesPerHour,
+        ChatSetting::QuietHours,
+        ChatSetting::Timezone,
+        ChatSetting::Locale,
+    ];
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            ChatSetting::AutoreplyChance => "autoreply_chance",
+            ChatSetting::StickerLruSize => "sticker_lru_size",
+            ChatSetting::ReplyBurst => "reply_burst",
+            ChatSetting::RepliesPerHour => "replies_per_hour",
+            ChatSetting::QuietHours => "quiet_hours",
+            ChatSetting::Timezone => "timezone",
+            ChatSetting::Locale => "locale",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ChatSetting::AutoreplyChance => "automaattisen vastauksen todennäköisyys (0-1)",
+            ChatSetting::StickerLruSize => "muistettavien tarrojen määrä per emoji (1-200)",
+            ChatSetting::ReplyBurst => {
+                "vastauksia putkeen ennen rajoitusta (0-100, 0 = ei rajoitusta)"
+            }
+            ChatSetting::RepliesPerHour => "vastauksia tunnissa rajoituksen jälkeen (1-3600)",
+            ChatSetting::QuietHours => {
+                "hiljaiset tunnit, jolloin botti ei vastaa pyytämättä (esim. 23:00-07:00,13:00-14:00 tai ei)"
+            }
+            ChatSetting::Timezone => "aikavyöhyke (esim. Europe/Helsinki)",
+            ChatSetting::Locale => "päivämäärien muoto (fi, en tai iso)",
+        }
+    }
+
+    pub fn get(&self, config: &ChatConfig) -> String {
+        match self {
+            ChatSetting::AutoreplyChance => config.autoreply_chance.to_string(),
+            ChatSetting::StickerLruSize => config.sticker_lru_size.to_string(),
+            ChatSetting::ReplyBurst => config.reply_burst.to_string(),
+            ChatSetting::RepliesPerHour => config.replies_per_hour.to_string(),
+            ChatSetting::QuietHours if config.quiet_hours.0.is_empty() => String::from("ei"),
+            ChatSetting::QuietHours => config.quiet_hours.to_string(),
+            ChatSetting::Timezone => config.timezone.name().to_string(),
+            ChatSetting::Locale => config.locale.as_str().to_string(),
+        }
+    }
+
+    pub fn set(&self, config: &mut ChatConfig, value: &str) -> Result<(), SettingError> {
+        match self {
+            ChatSetting::AutoreplyChance => {
+                config.autoreply_chance = self.parse_in_range(value, 0.0, 1.0)?;
+            }
+            ChatSetting::StickerLruSize => {
+                config.sticker_lru_size = self.parse_in_range(value, 1, 200)?;
+            }
+            ChatSetting::ReplyBurst => {
+                config.reply_burst = self.parse_in_range(value, 0, 100)?;
+            }
+            ChatSetting::RepliesPerHour => {
+                config.replies_per_hour = self.parse_in_range(value, 1, 3600)?;
+            }
+            ChatSetting::QuietHours => {
+                let value = match value.trim() {
+                    "ei" | "none" => "",
+                    value => value,
+                };
+
+                config.quiet_hours =
+                    QuietHours::from_str(value).map_err(|_| SettingError::InvalidValue {
+                        key: self.key(),
+                        expected: String::from("käytä muotoa HH:MM-HH:MM,HH:MM-HH:MM"),
+                    })?;
+            }
+            ChatSetting::Timezone => {
+                config.timezone =
+                    // Zone names use underscores, e.g. America/New_York
+                    Tz::from_str(&value.trim().replace(' ', "_")).map_err(|_| SettingError::InvalidValue {
+                        key: self.key(),
+                        expected: String::from("käytä IANA-aikavyöhykettä, esim. Europe/Helsinki"),
+                    })?;
+            }
+            ChatSetting::Locale => {
+                config.locale =
+                    DateLocale::from_str(value.trim()).map_err(|_| SettingError::InvalidValue {
+                        key: self.key(),
+                        expected: String::from("käytä jotain seuraavista: fi, en, iso"),
+                    })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_in_range<T>(&self, value: &str, min: T, max: T) -> Result<T, SettingError>
+    where
+        T: FromStr + PartialOrd + Display,
+    {
+        match value.trim().parse::<T>() {
+            Ok(value) if min <= value && value <= max => Ok(value),
+            _ => Err(SettingError::InvalidValue {
+                key: self.key(),
+                expected: format!("pitää olla väliltä {}-{}", min, max),
+            }),
         }
     }
 }
@@ -64,20 +307,64 @@ impl ChatConfigModel {
         Ok(config)
     }
 
-    pub async fn set_autoreply_chance(&self, chat_id: ChatId, chance: f64) -> anyhow::Result<()> {
+    pub async fn save(&self, config: ChatConfig) -> anyhow::Result<()> {
         self.db
-            .set_autoreply_chance(chat_id, chance)
+            .save_chat_config(&config)
             .await
-            .context("Failed to update autoreply chance")?;
+            .context("Failed to save chat config")?;
 
         {
             let mut writer = self.cache.write().await;
-            writer
-                .entry(chat_id)
-                .or_insert_with(|| ChatConfig::new(chat_id))
-                .autoreply_chance = chance;
+            writer.insert(config.chat_id, config);
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(setting: ChatSetting, value: &str) -> Result<ChatConfig, SettingError> {
+        let mut config = ChatConfig::new(ChatId(1));
+        setting.set(&mut config, value).map(|_| config)
+    }
+
+    #[test]
+    fn reject_nan_and_out_of_range_numbers() {
+        assert!(set(ChatSetting::AutoreplyChance, "NaN").is_err());
+        assert!(set(ChatSetting::AutoreplyChance, "-0.1").is_err());
+        assert!(set(ChatSetting::AutoreplyChance, "1.5").is_err());
+        assert!(set(ChatSetting::StickerLruSize, "-1").is_err());
+        assert!(set(ChatSetting::StickerLruSize, "201").is_err());
+        assert!(set(ChatSetting::RepliesPerHour, "0").is_err());
+        assert!(set(ChatSetting::ReplyBurst, "kaksi").is_err());
+
+        let config = set(ChatSetting::AutoreplyChance, " 0.25 ").unwrap();
+        assert_eq!(config.autoreply_chance, 0.25);
+    }
+
+    #[test]
+    fn parse_timezone() {
+        let config = set(ChatSetting::Timezone, "America/New York").unwrap();
+        assert_eq!(config.timezone, chrono_tz::America::New_York);
+
+        assert!(set(ChatSetting::Timezone, "Europe/Helsingfors").is_err());
+        assert!(set(ChatSetting::Timezone, "+03:00").is_err());
+        assert!(set(ChatSetting::Timezone, "").is_err());
+    }
+
+    #[test]
+    fn parse_quiet_hours() {
+        let config = set(ChatSetting::QuietHours, "23:00-07:00, 13:00-14:00").unwrap();
+        assert_eq!(config.quiet_hours.to_string(), "23:00-07:00,13:00-14:00");
+
+        let config = set(ChatSetting::QuietHours, "ei").unwrap();
+        assert!(config.quiet_hours.0.is_empty());
+
+        assert!(set(ChatSetting::QuietHours, "23:00").is_err());
+        assert!(set(ChatSetting::QuietHours, "25:00-07:00").is_err());
+        assert!(set(ChatSetting::QuietHours, "23:00-07:00,iltaisin").is_err());
+    }
+}
@@ -0,0 +1,238 @@
+use std::{ops::RangeInclusive, sync::Arc};
+
+use anyhow::Context;
+use chrono::{Duration, NaiveDate, Utc};
+use futures::future::BoxFuture;
+use reqwest::Url;
+use scraper::{Html, Selector};
+
+use super::{check_archive, http::HttpClient, Comic, ComicSource};
+
+fn extract_comic_url(html: &str) -> anyhow::Result<String> {
+    let document = Html::parse_document(html);
+    let selector = Selector::parse(".item-comic-image > img").unwrap();
+
+    let cartoon = document
+        .select(&selector)
+        .next()
+        .context("Failed to find comic element")?;
+
+    let srcset = cartoon
+        .value()
+        .attr("data-srcset")
+        .context("Failed to find data-srcset attribute")?;
+
+    let url = srcset
+        .split(' ')
+        .next()
+        .context("Failed to extract URL from data-srcset")?;
+
+    Ok(String::from(url))
+}
+
+/// Parses the date from a comic page URL, e.g. `https://www.gocomics.com/garfield/1988/06/19`.
+fn date_from_page_url(url: &Url) -> Option<NaiveDate> {
+    let segments = url.path_segments()?.collect::<Vec<_>>();
+
+    match segments[..] {
+        [.., year, month, day] => {
+            NaiveDate::from_ymd_opt(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?)
+        }
+        _ => None,
+    }
+}
+
+/// Comic source for a strip on gocomics.com.
+pub struct GoComics {
+    http: Arc<dyn HttpClient>,
+    id: &'static str,
+    name: &'static str,
+    /// The strip's path on gocomics.com, e.g. `garfield`.
+    slug: &'static str,
+    /// Date of the first strip in the archive.
+    first_date: NaiveDate,
+}
+
+impl GoComics {
+    pub fn new(
+        http: Arc<dyn HttpClient>,
+        id: &'static str,
+        name: &'static str,
+        slug: &'static str,
+        first_date: NaiveDate,
+    ) -> Self {
+        Self {
+            http,
+            id,
+            name,
+            slug,
+            first_date,
+        }
+    }
+
+    async fn fetch_comic_for_page_url(&self, url: &str) -> anyhow::Result<Comic> {
+        let page = self.http.get(url).await?;
+        // Random strips redirect to the page of the strip
+        let date = date_from_page_url(&page.url);
+
+        let url = extract_comic_url(&page.body).context("Failed to extract comic URL")?;
+        let image_url = Url::parse(&url).context("Failed to parse comic URL")?;
+
+        Ok(Comic::new(image_url, date))
+    }
+}
+
+impl ComicSource for GoComics {
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn latest(&self) -> BoxFuture<'_, anyhow::Result<Comic>> {
+        let yesterday = Utc::now().date_naive() - Duration::days(1);
+        self.by_date(yesterday)
+    }
+
+    fn latest_date(&self) -> Option<NaiveDate> {
+        // Technically fetch yesterdays comic to be safe
+        Some(Utc::now().date_naive() - Duration::days(1))
+    }
+
+    fn random(&self) -> BoxFuture<'_, anyhow::Result<Comic>> {
+        Box::pin(async {
+            let url = format!("https://www.gocomics.com/random/{}", self.slug);
+            self.fetch_comic_for_page_url(&url)
+                .await
+                .with_context(|| format!("Failed to fetch random {}", self.name))
+        })
+    }
+
+    fn supports_dates(&self) -> bool {
+        true
+    }
+
+    fn archive(&self) -> BoxFuture<'_, anyhow::Result<RangeInclusive<NaiveDate>>> {
+        Box::pin(async { Ok(self.first_date..=Utc::now().date_naive()) })
+    }
+
+    fn by_date(&self, date: NaiveDate) -> BoxFuture<'_, anyhow::Result<Comic>> {
+        Box::pin(async move {
+            check_archive(self.name, date, self.archive().await?)?;
+
+            let url = format!(
+                "https://www.gocomics.com/{}/{}",
+                self.slug,
+                date.format("%Y/%m/%d")
+            );
+            self.fetch_comic_for_page_url(&url)
+                .await
+                .with_context(|| format!("Failed to fetch {} for {}", self.name, date))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comics::{
+        http::{serve_locally, LocalRoute, ReqwestClient, StandInClient},
+        ComicError,
+    };
+
+    const PAGE: &str = include_str!("fixtures/gocomics_page.html");
+
+    fn stand_in_garfield() -> GoComics {
+        let http = StandInClient(|url: &str| {
+            (url == "https://www.gocomics.com/garfield/1988/06/19").then(|| PAGE.to_string())
+        });
+
+        GoComics::new(
+            Arc::new(http),
+            "garfield",
+            "Garfield",
+            "garfield",
+            NaiveDate::from_ymd(1978, 6, 19),
+        )
+    }
+
+    #[test]
+    fn extract_url_from_fixture() {
+        assert_eq!(
+            extract_comic_url(PAGE).unwrap(),
+            "https://assets.amuniversal.com/fixture-garfield-1988-06-19"
+        );
+    }
+
+    #[test]
+    fn fail_on_page_without_comic() {
+        assert!(extract_comic_url("<html><body>Not found</body></html>").is_err());
+    }
+
+    #[test]
+    fn parse_date_from_page_url() {
+        let url = Url::parse("https://www.gocomics.com/garfield/1988/06/19").unwrap();
+        assert_eq!(
+            date_from_page_url(&url),
+            Some(NaiveDate::from_ymd(1988, 6, 19))
+        );
+
+        let url = Url::parse("https://www.gocomics.com/random/garfield").unwrap();
+        assert_eq!(date_from_page_url(&url), None);
+    }
+
+    #[tokio::test]
+    async fn fetch_by_date_from_stand_in() {
+        let garfield = stand_in_garfield();
+
+        let comic = garfield
+            .by_date(NaiveDate::from_ymd(1988, 6, 19))
+            .await
+            .unwrap();
+        assert_eq!(
+            comic.image_url.as_str(),
+            "https://assets.amuniversal.com/fixture-garfield-1988-06-19"
+        );
+        assert_eq!(comic.date, Some(NaiveDate::from_ymd(1988, 6, 19)));
+
+        let err = garfield
+            .by_date(NaiveDate::from_ymd(1978, 6, 18))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ComicError>(),
+            Some(ComicError::OutsideArchive { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn follow_random_redirect_over_http() {
+        let base_url = serve_locally(vec![
+            (
+                "/random/garfield",
+                LocalRoute::Redirect("/garfield/1988/06/19"),
+            ),
+            ("/garfield/1988/06/19", LocalRoute::Page(PAGE)),
+        ])
+        .await;
+        let garfield = GoComics::new(
+            Arc::new(ReqwestClient::new()),
+            "garfield",
+            "Garfield",
+            "garfield",
+            NaiveDate::from_ymd(1978, 6, 19),
+        );
+
+        let comic = garfield
+            .fetch_comic_for_page_url(&format!("{base_url}/random/garfield"))
+            .await
+            .unwrap();
+        assert_eq!(
+            comic.image_url.as_str(),
+            "https://assets.amuniversal.com/fixture-garfield-1988-06-19"
+        );
+        assert_eq!(comic.date, Some(NaiveDate::from_ymd(1988, 6, 19)));
+    }
+}
@@ -0,0 +1,559 @@
+use std::{
+    cmp::Ordering,
+    marker::PhantomData,
+    ops::RangeInclusive,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use chrono::NaiveDate;
+use futures::future::BoxFuture;
+use once_cell::sync::OnceCell;
+use rand::Rng;
+use regex::Regex;
+use reqwest::Url;
+use scraper::{Html, Selector};
+
+use super::{check_archive, http::HttpClient, Comic, ComicError, ComicSource};
+
+fn extract_cartoon_url(html: &str) -> anyhow::Result<Url> {
+    let document = Html::parse_document(html);
+    let selector = Selector::parse(".cartoon img").unwrap();
+    let cartoon = document
+        .select(&selector)
+        .next()
+        .context("failed to find cartoon element")?;
+
+    let srcset = cartoon
+        .value()
+        .attr("data-srcset")
+        .context("Failed to find data-srcset attribute")?;
+
+    let src = srcset
+        .split(' ')
+        .next()
+        .context("Failed to extract URL from data-srcset")?;
+    let url = format!("https:{src}");
+
+    let url = Url::from_str(&url).with_context(|| format!("Failed to parse URL '{}'", url))?;
+
+    Ok(url)
+}
+
+static FINNISH_DATE_REGEX: OnceCell<Regex> = OnceCell::new();
+
+/// Extracts the publication date of the first cartoon on a lane page.
+fn extract_cartoon_date(html: &str) -> anyhow::Result<NaiveDate> {
+    let document = Html::parse_document(html);
+    let selector = Selector::parse("time[datetime]").unwrap();
+
+    // e.g. <time datetime="2022-09-18T02:00:00+03:00">
+    let datetime = document
+        .select(&selector)
+        .next()
+        .and_then(|time| time.value().attr("datetime"));
+
+    if let Some(date) = datetime
+        .and_then(|datetime| datetime.get(..10))
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+    {
+        return Ok(date);
+    }
+
+    // Otherwise look for a date in the text, e.g. 18.9.2022
+    let text = document.root_element().text().collect::<Vec<_>>().join(" ");
+    let regex = FINNISH_DATE_REGEX
+        .get_or_init(|| Regex::new(r"\b(\d{1,2})\.(\d{1,2})\.(\d{4})\b").unwrap());
+    let captures = regex
+        .captures(&text)
+        .context("Failed to find cartoon date")?;
+
+    NaiveDate::from_ymd_opt(
+        captures[3].parse()?,
+        captures[2].parse()?,
+        captures[1].parse()?,
+    )
+    .with_context(|| format!("Invalid cartoon date '{}'", &captures[0]))
+}
+
+pub trait HsCartoonExtractor {
+    const ID: &'static str;
+
+    const NAME: &'static str;
+
+    const PAGED_URL: &'static str;
+
+    /// Rough number of pages in the archive. The actual number is discovered starting from this.
+    const PAGES_ESTIMATE: u32;
+
+    /// Pages are ordered from the newest cartoon to the oldest.
+    fn get_page_url(offset: u32) -> String {
+        format!("{}&from={}", Self::PAGED_URL, offset)
+    }
+
+    fn get_latest_page_url() -> String {
+        Self::get_page_url(0)
+    }
+}
+
+pub struct Fingerpori;
+
+impl HsCartoonExtractor for Fingerpori {
+    const ID: &'static str = "fingerpori";
+
+    const NAME: &'static str = "Fingerpori";
+
+    const PAGED_URL: &'static str =
+        "https://www.hs.fi/rest/laneitems/39221/moreItems?pageId=290&even=false";
+
+    const PAGES_ESTIMATE: u32 = 480;
+}
+
+pub struct Fokit;
+
+impl HsCartoonExtractor for Fokit {
+    const ID: &'static str = "fokit";
+
+    const NAME: &'static str = "Fok_It";
+
+    const PAGED_URL: &'static str =
+        "https://www.hs.fi/rest/laneitems/39221/moreItems?pageId=295&even=false";
+
+    const PAGES_ESTIMATE: u32 = 499;
+}
+
+/// How long a discovered archive length is trusted before discovering it again.
+const LAST_PAGE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Discovery gives up growing the archive past this many pages.
+const MAX_PAGES: u32 = 1 << 16;
+
+/// How many random pages are tried before giving up, in case the archive has shrunk.
+const RANDOM_PAGE_ATTEMPTS: u32 = 3;
+
+/// How long the latest cartoon is reused before checking for a newer one.
+const LATEST_MAX_AGE: Duration = Duration::from_secs(15 * 60);
+
+/// Comic source for a cartoon lane of Helsingin Sanomat.
+pub struct HsLane<E> {
+    http: Arc<dyn HttpClient>,
+    /// Offset of the oldest page with a cartoon and when it was discovered.
+    last_page: Mutex<Option<(u32, Instant)>>,
+    /// The latest cartoon and when it was fetched.
+    latest: Mutex<Option<(Comic, Instant)>>,
+    extractor: PhantomData<fn() -> E>,
+}
+
+impl<E: HsCartoonExtractor> HsLane<E> {
+    pub fn new(http: Arc<dyn HttpClient>) -> Self {
+        Self {
+            http,
+            last_page: Mutex::new(None),
+            latest: Mutex::new(None),
+            extractor: PhantomData,
+        }
+    }
+
+    /// Returns the offset of the oldest page with a cartoon, discovering it if needed.
+    async fn last_page(&self) -> anyhow::Result<u32> {
+        if let Some((last_page, discovered_at)) = *self.last_page.lock().unwrap() {
+            if discovered_at.elapsed() < LAST_PAGE_MAX_AGE {
+                return Ok(last_page);
+            }
+        }
+
+        let last_page = self
+            .discover_last_page()
+            .await
+            .with_context(|| format!("Failed to discover {} archive length", E::NAME))?;
+        log::info!("{} archive has {} pages", E::NAME, last_page + 1);
+
+        *self.last_page.lock().unwrap() = Some((last_page, Instant::now()));
+
+        Ok(last_page)
+    }
+
+    /// Forgets the archive length so that it is discovered again on next use.
+    fn invalidate_last_page(&self) {
+        *self.last_page.lock().unwrap() = None;
+    }
+
+    async fn has_cartoon(&self, offset: u32) -> anyhow::Result<bool> {
+        let html = self.fetch_page(&E::get_page_url(offset)).await?;
+
+        Ok(extract_cartoon_url(&html).is_ok())
+    }
+
+    /// Finds the oldest page with a cartoon by growing the estimate until a page is empty
+    /// and then binary searching between the last page with a cartoon and the first empty one.
+    async fn discover_last_page(&self) -> anyhow::Result<u32> {
+        if !self.has_cartoon(0).await? {
+            anyhow::bail!("The first page has no cartoon");
+        }
+
+        let mut present = 0;
+        let mut empty = E::PAGES_ESTIMATE.max(1);
+
+        while self.has_cartoon(empty).await? {
+            if empty >= MAX_PAGES {
+                return Ok(empty);
+            }
+
+            present = empty;
+            empty = (empty * 2).min(MAX_PAGES);
+        }
+
+        while empty - present > 1 {
+            let offset = present + (empty - present) / 2;
+
+            if self.has_cartoon(offset).await? {
+                present = offset;
+            } else {
+                empty = offset;
+            }
+        }
+
+        Ok(present)
+    }
+
+    async fn fetch_page(&self, page_url: &str) -> anyhow::Result<String> {
+        Ok(self.http.get(page_url).await?.body)
+    }
+
+    async fn fetch_comic(&self, page_url: &str) -> anyhow::Result<Comic> {
+        let html = self.fetch_page(page_url).await?;
+
+        let image_url = extract_cartoon_url(&html).context("Failed to extract cartoon URL")?;
+        // The date is only needed for caching, so a missing one is not an error here
+        let date = extract_cartoon_date(&html).ok();
+
+        Ok(Comic::new(image_url, date))
+    }
+
+    async fn fetch_date(&self, page_url: &str) -> anyhow::Result<NaiveDate> {
+        let html = self.fetch_page(page_url).await?;
+
+        extract_cartoon_date(&html).context("Failed to extract cartoon date")
+    }
+}
+
+impl<E: HsCartoonExtractor> ComicSource for HsLane<E> {
+    fn id(&self) -> &'static str {
+        E::ID
+    }
+
+    fn name(&self) -> &'static str {
+        E::NAME
+    }
+
+    fn latest(&self) -> BoxFuture<'_, anyhow::Result<Comic>> {
+        Box::pin(async {
+            if let Some((comic, fetched_at)) = &*self.latest.lock().unwrap() {
+                if fetched_at.elapsed() < LATEST_MAX_AGE {
+                    return Ok(comic.clone());
+                }
+            }
+
+            let comic = self
+                .fetch_comic(&E::get_latest_page_url())
+                .await
+                .with_context(|| format!("Failed to fetch latest {}", E::NAME))?;
+
+            *self.latest.lock().unwrap() = Some((comic.clone(), Instant::now()));
+
+            Ok(comic)
+        })
+    }
+
+    fn random(&self) -> BoxFuture<'_, anyhow::Result<Comic>> {
+        Box::pin(async {
+            let mut attempt = 1;
+
+            loop {
+                let last_page = self.last_page().await?;
+                let offset = rand::thread_rng().gen_range(0..=last_page);
+
+                match self.fetch_comic(&E::get_page_url(offset)).await {
+                    Ok(comic) => return Ok(comic),
+                    // An empty page means the archive has shrunk since it was discovered
+                    Err(err) if attempt < RANDOM_PAGE_ATTEMPTS => {
+                        log::warn!(
+                            "Failed to fetch random {} from page {}: {:#}",
+                            E::NAME,
+                            offset,
+                            err
+                        );
+                        self.invalidate_last_page();
+                        attempt += 1;
+                    }
+                    Err(err) => {
+                        return Err(err)
+                            .with_context(|| format!("Failed to fetch random {}", E::NAME))
+                    }
+                }
+            }
+        })
+    }
+
+    fn supports_dates(&self) -> bool {
+        true
+    }
+
+    fn archive(&self) -> BoxFuture<'_, anyhow::Result<RangeInclusive<NaiveDate>>> {
+        Box::pin(async {
+            let last_page = self.last_page().await?;
+            let last = self.fetch_date(&E::get_latest_page_url()).await?;
+            let first = self.fetch_date(&E::get_page_url(last_page)).await?;
+
+            Ok(first..=last)
+        })
+    }
+
+    fn by_date(&self, date: NaiveDate) -> BoxFuture<'_, anyhow::Result<Comic>> {
+        Box::pin(async move {
+            let archive = self
+                .archive()
+                .await
+                .with_context(|| format!("Failed to fetch {} archive", E::NAME))?;
+            check_archive(E::NAME, date, archive)?;
+
+            // Binary search through the pages, newest first
+            let mut low = 0;
+            let mut high = self.last_page().await?;
+
+            while low <= high {
+                let offset = low + (high - low) / 2;
+                let html = self
+                    .fetch_page(&E::get_page_url(offset))
+                    .await
+                    .with_context(|| format!("Failed to fetch {} page {}", E::NAME, offset))?;
+                let page_date = extract_cartoon_date(&html)
+                    .with_context(|| format!("Failed to extract {} date", E::NAME))?;
+
+                match page_date.cmp(&date) {
+                    Ordering::Equal => {
+                        let image_url =
+                            extract_cartoon_url(&html).context("Failed to extract cartoon URL")?;
+                        return Ok(Comic::new(image_url, Some(date)));
+                    }
+                    Ordering::Greater => low = offset + 1,
+                    Ordering::Less if offset == 0 => break,
+                    Ordering::Less => high = offset - 1,
+                }
+            }
+
+            Err(ComicError::NotPublished {
+                name: E::NAME,
+                date,
+            }
+            .into())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+    use super::*;
+    use crate::comics::{
+        fetch_first_published,
+        http::{serve_locally, LocalRoute, ReqwestClient, StandInClient},
+        ComicRequest,
+    };
+
+    const LANE_PAGE: &str = include_str!("fixtures/hs_lane_page.html");
+    const LANE_PAGE_TEXT_DATE: &str = include_str!("fixtures/hs_lane_page_text_date.html");
+    const LANE_PAGE_EMPTY: &str = include_str!("fixtures/hs_lane_page_empty.html");
+
+    /// Newest date in the stand-in lane, which has a cartoon every other day.
+    fn newest_date() -> NaiveDate {
+        NaiveDate::from_ymd(2022, 9, 18)
+    }
+
+    /// Stand-in lane with cartoons on pages up to `last_page`.
+    fn stand_in_lane<E: HsCartoonExtractor>(last_page: u32) -> HsLane<E> {
+        let http = StandInClient(move |url: &str| {
+            let offset: u32 = url.rsplit_once("&from=")?.1.parse().ok()?;
+
+            if offset > last_page {
+                return Some(String::from(LANE_PAGE_EMPTY));
+            }
+
+            let date = newest_date() - chrono::Duration::days(2 * i64::from(offset));
+
+            Some(format!(
+                r#"<div class="cartoon"><img data-srcset="//hs.example/img/{offset}.jpg 468w"></div><time datetime="{date}T02:00:00+03:00"></time>"#
+            ))
+        });
+
+        HsLane::new(Arc::new(http))
+    }
+
+    struct SmallEstimate;
+
+    impl HsCartoonExtractor for SmallEstimate {
+        const ID: &'static str = "small";
+
+        const NAME: &'static str = "Small";
+
+        const PAGED_URL: &'static str = "https://hs.example/lane?pageId=1";
+
+        const PAGES_ESTIMATE: u32 = 10;
+    }
+
+    #[test]
+    fn extract_url_from_fixture() {
+        assert_eq!(
+            extract_cartoon_url(LANE_PAGE).unwrap().as_str(),
+            "https://hs.mediadelivery.fi/img/468/fixture-fingerpori-2022-09-18.jpg"
+        );
+    }
+
+    #[test]
+    fn extract_date_from_fixture() {
+        assert_eq!(
+            extract_cartoon_date(LANE_PAGE).unwrap(),
+            NaiveDate::from_ymd(2022, 9, 18)
+        );
+        assert_eq!(
+            extract_cartoon_date(LANE_PAGE_TEXT_DATE).unwrap(),
+            NaiveDate::from_ymd(2022, 9, 4)
+        );
+    }
+
+    #[test]
+    fn fail_on_empty_page() {
+        assert!(extract_cartoon_url(LANE_PAGE_EMPTY).is_err());
+        assert!(extract_cartoon_date(LANE_PAGE_EMPTY).is_err());
+    }
+
+    #[tokio::test]
+    async fn fetch_fixture_over_http() {
+        let base_url = serve_locally(vec![("/lane", LocalRoute::Page(LANE_PAGE))]).await;
+        let lane = HsLane::<Fingerpori>::new(Arc::new(ReqwestClient::new()));
+
+        let comic = lane.fetch_comic(&format!("{base_url}/lane")).await.unwrap();
+        assert_eq!(
+            comic.image_url.as_str(),
+            "https://hs.mediadelivery.fi/img/468/fixture-fingerpori-2022-09-18.jpg"
+        );
+        assert_eq!(comic.date, Some(NaiveDate::from_ymd(2022, 9, 18)));
+
+        assert!(lane
+            .fetch_comic(&format!("{base_url}/missing"))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn fetch_latest_from_stand_in() {
+        let comic = stand_in_lane::<Fingerpori>(300).latest().await.unwrap();
+
+        assert_eq!(comic.image_url.as_str(), "https://hs.example/img/0.jpg");
+        assert_eq!(comic.date, Some(newest_date()));
+    }
+
+    #[tokio::test]
+    async fn search_by_date_from_stand_in() {
+        let lane = stand_in_lane::<Fingerpori>(300);
+
+        let comic = lane
+            .by_date(newest_date() - chrono::Duration::days(2 * 123))
+            .await
+            .unwrap();
+        assert_eq!(comic.image_url.as_str(), "https://hs.example/img/123.jpg");
+
+        let err = lane
+            .by_date(newest_date() - chrono::Duration::days(1))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ComicError>(),
+            Some(ComicError::NotPublished { .. })
+        ));
+
+        let err = lane
+            .by_date(newest_date() + chrono::Duration::days(1))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ComicError>(),
+            Some(ComicError::OutsideArchive { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn skip_years_without_a_strip() {
+        // Cartoons are published every other day, so there is none a year before the newest one
+        let lane = stand_in_lane::<Fingerpori>(400);
+        let year_ago = NaiveDate::from_ymd(2021, 9, 18);
+        let two_years_ago = NaiveDate::from_ymd(2020, 9, 18);
+
+        let comic = fetch_first_published(&lane, vec![year_ago, two_years_ago])
+            .await
+            .unwrap();
+        assert_eq!(comic.date, Some(two_years_ago));
+
+        let comic = lane
+            .fetch(ComicRequest::OnThisDay, newest_date())
+            .await
+            .unwrap();
+        assert_eq!(comic.date, Some(two_years_ago));
+
+        let err = fetch_first_published(&lane, vec![year_ago])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ComicError>(),
+            Some(ComicError::NoHistory { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn discover_shrunk_archive() {
+        let lane = stand_in_lane::<Fingerpori>(300);
+        assert_eq!(lane.last_page().await.unwrap(), 300);
+    }
+
+    #[tokio::test]
+    async fn discover_grown_archive() {
+        let lane = stand_in_lane::<SmallEstimate>(1234);
+        assert_eq!(lane.last_page().await.unwrap(), 1234);
+
+        let lane = stand_in_lane::<SmallEstimate>(0);
+        assert_eq!(lane.last_page().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn search_oldest_date_from_stand_in() {
+        let lane = stand_in_lane::<SmallEstimate>(40);
+        let archive = lane.archive().await.unwrap();
+        assert_eq!(
+            *archive.start(),
+            newest_date() - chrono::Duration::days(2 * 40)
+        );
+
+        let comic = lane.by_date(*archive.start()).await.unwrap();
+        assert_eq!(comic.image_url.as_str(), "https://hs.example/img/40.jpg");
+    }
+
+    #[tokio::test]
+    async fn reuse_latest_for_a_while() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let http = StandInClient(move |_: &str| {
+            counter.fetch_add(1, AtomicOrdering::SeqCst);
+            Some(String::from(LANE_PAGE))
+        });
+        let lane = HsLane::<Fingerpori>::new(Arc::new(http));
+
+        let first = lane.latest().await.unwrap();
+        let second = lane.latest().await.unwrap();
+
+        assert_eq!(first.image_url, second.image_url);
+        assert_eq!(requests.load(AtomicOrdering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::Context;
+use futures::future::BoxFuture;
+use reqwest::Url;
+
+/// A fetched page.
+pub struct Page {
+    /// The final URL after redirects.
+    pub url: Url,
+    pub body: String,
+}
+
+/// Fetches the pages comics are scraped from, so that tests can serve fixtures instead.
+pub trait HttpClient: Send + Sync {
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, anyhow::Result<Page>>;
+
+    fn get_bytes<'a>(&'a self, url: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<u8>>>;
+}
+
+pub struct ReqwestClient(reqwest::Client);
+
+impl ReqwestClient {
+    pub fn new() -> Self {
+        Self(reqwest::Client::new())
+    }
+}
+
+impl HttpClient for ReqwestClient {
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, anyhow::Result<Page>> {
+        Box::pin(async move {
+            let response = self.0.get(url).send().await.context("Failed to fetch")?;
+            let url = response.url().clone();
+            let body = response.text().await.context("Failed to fetch (body)")?;
+
+            Ok(Page { url, body })
+        })
+    }
+
+    fn get_bytes<'a>(&'a self, url: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<u8>>> {
+        Box::pin(async move {
+            let bytes = self
+                .0
+                .get(url)
+                .send()
+                .await
+                .context("Failed to fetch")?
+                .error_for_status()?
+                .bytes()
+                .await
+                .context("Failed to fetch (body)")?;
+
+            Ok(bytes.to_vec())
+        })
+    }
+}
+
+/// Stand-in for the comic sites, serving pages generated from the requested URL.
+#[cfg(test)]
+pub struct StandInClient<F>(pub F);
+
+#[cfg(test)]
+impl<F> HttpClient for StandInClient<F>
+where
+    F: Fn(&str) -> Option<String> + Send + Sync,
+{
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, anyhow::Result<Page>> {
+        Box::pin(async move {
+            let body = (self.0)(url).with_context(|| format!("Not found: {}", url))?;
+
+            Ok(Page {
+                url: Url::parse(url)?,
+                body,
+            })
+        })
+    }
+
+    fn get_bytes<'a>(&'a self, url: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<u8>>> {
+        Box::pin(async move { Ok(self.get(url).await?.body.into_bytes()) })
+    }
+}
+
+/// How the local listener answers a path.
+#[cfg(test)]
+pub enum LocalRoute {
+    Page(&'static str),
+    Redirect(&'static str),
+}
+
+/// Serves `routes` over plain HTTP on a local port, so that tests can go through `ReqwestClient`.
+/// Returns the base URL; unknown paths get a 404.
+#[cfg(test)]
+pub async fn serve_locally(routes: Vec<(&'static str, LocalRoute)>) -> String {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let routes = std::sync::Arc::new(routes);
+
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let routes = routes.clone();
+
+            tokio::spawn(async move {
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+
+                while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                    match stream.read(&mut buffer).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(read) => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+
+                // e.g. GET /garfield/1988/06/19 HTTP/1.1
+                let request = String::from_utf8_lossy(&request);
+                let path = request.split(' ').nth(1).unwrap_or_default();
+
+                let response = match routes.iter().find(|(route, _)| *route == path) {
+                    Some((_, LocalRoute::Page(body))) => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    ),
+                    Some((_, LocalRoute::Redirect(location))) => format!(
+                        "HTTP/1.1 302 Found\r\nLocation: {location}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    ),
+                    None => String::from(
+                        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    ),
+                };
+
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+
+    format!("http://{address}")
+}
@@ -0,0 +1,352 @@
+use std::{ops::RangeInclusive, str::FromStr, sync::Arc};
+
+use chrono::{Datelike, NaiveDate};
+use futures::future::BoxFuture;
+use rand::seq::SliceRandom;
+use reqwest::Url;
+use thiserror::Error;
+
+mod gocomics;
+mod hs;
+mod http;
+
+use gocomics::GoComics;
+use hs::{Fingerpori, Fokit, HsLane};
+use http::{HttpClient, ReqwestClient};
+
+/// Which strip of a comic to fetch.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ComicRequest {
+    Latest,
+    Random,
+    Date(NaiveDate),
+    /// The strip of this day the given number of years ago.
+    YearsAgo(u32),
+    /// The strip of this day from a random earlier year in the archive.
+    OnThisDay,
+}
+
+impl ComicRequest {
+    /// Whether the request needs `ComicSource::by_date`.
+    pub fn needs_dates(&self) -> bool {
+        !matches!(self, ComicRequest::Latest | ComicRequest::Random)
+    }
+
+    /// Whether the request picks a strip at random.
+    pub fn is_random(&self) -> bool {
+        matches!(self, ComicRequest::Random | ComicRequest::OnThisDay)
+    }
+
+    /// The date of the requested strip, if it is known without fetching anything.
+    pub fn date(&self, today: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            ComicRequest::Date(date) => Some(*date),
+            ComicRequest::YearsAgo(years) => years_before(today, *years),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for ComicRequest {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" | "latest" | "uusin" => Ok(ComicRequest::Latest),
+            "random" | "satunnainen" => Ok(ComicRequest::Random),
+            "history" | "historia" => Ok(ComicRequest::OnThisDay),
+            s => {
+                // e.g. 10v or 10y for this day ten years ago
+                let years = s.strip_suffix('v').or_else(|| s.strip_suffix('y'));
+
+                match years.and_then(|years| years.parse::<u32>().ok()) {
+                    Some(years) if years > 0 => Ok(ComicRequest::YearsAgo(years)),
+                    _ => NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                        .map(ComicRequest::Date)
+                        .map_err(|_| anyhow::anyhow!("Invalid comic request: {}", s)),
+                }
+            }
+        }
+    }
+}
+
+/// Errors worth telling the user about as is.
+#[derive(Debug, Error)]
+pub enum ComicError {
+    #[error("Sarjakuvan {name} arkistossa on päivät {} – {}, joten päivää {date} ei löydy.", .archive.start(), .archive.end())]
+    OutsideArchive {
+        name: &'static str,
+        date: NaiveDate,
+        archive: RangeInclusive<NaiveDate>,
+    },
+    #[error("Sarjakuvaa {name} ei julkaistu päivänä {date}.")]
+    NotPublished { name: &'static str, date: NaiveDate },
+    #[error("Sarjakuvan {name} arkistossa ei ole tätä päivää aiemmilta vuosilta.")]
+    NoHistory { name: &'static str },
+}
+
+/// Returns the same day the given number of years before, or the 28th for February 29th.
+fn years_before(date: NaiveDate, years: u32) -> Option<NaiveDate> {
+    let year = date.year() - i32::try_from(years).ok()?;
+
+    NaiveDate::from_ymd_opt(year, date.month(), date.day())
+        .or_else(|| NaiveDate::from_ymd_opt(year, date.month(), date.day() - 1))
+}
+
+#[derive(Clone, Debug)]
+pub struct Comic {
+    pub image_url: Url,
+    /// Publication date, when the source tells it.
+    pub date: Option<NaiveDate>,
+    /// Telegram file_id of an earlier upload of the same strip, from the cache.
+    pub file_id: Option<String>,
+}
+
+impl Comic {
+    pub fn new(image_url: Url, date: Option<NaiveDate>) -> Self {
+        Self {
+            image_url,
+            date,
+            file_id: None,
+        }
+    }
+}
+
+// The fetches return boxed futures because traits cannot have async methods.
+pub trait ComicSource: Send + Sync {
+    /// Identifier used in commands, e.g. `fingerpori`.
+    fn id(&self) -> &'static str;
+
+    /// Display name, e.g. `Fingerpori`.
+    fn name(&self) -> &'static str;
+
+    fn latest(&self) -> BoxFuture<'_, anyhow::Result<Comic>>;
+
+    /// The date of the strip `latest` returns, if it is known without fetching anything.
+    fn latest_date(&self) -> Option<NaiveDate> {
+        None
+    }
+
+    fn random(&self) -> BoxFuture<'_, anyhow::Result<Comic>>;
+
+    /// Whether `by_date` is supported.
+    fn supports_dates(&self) -> bool {
+        false
+    }
+
+    fn by_date(&self, date: NaiveDate) -> BoxFuture<'_, anyhow::Result<Comic>> {
+        Box::pin(async move {
+            anyhow::bail!(
+                "{} does not support fetching by date ({})",
+                self.name(),
+                date
+            )
+        })
+    }
+
+    /// The dates `by_date` can fetch.
+    fn archive(&self) -> BoxFuture<'_, anyhow::Result<RangeInclusive<NaiveDate>>> {
+        Box::pin(async { anyhow::bail!("{} does not have an archive", self.name()) })
+    }
+
+    /// Fetches the requested strip, with dates relative to `today`.
+    fn fetch(
+        &self,
+        request: ComicRequest,
+        today: NaiveDate,
+    ) -> BoxFuture<'_, anyhow::Result<Comic>> {
+        match request {
+            ComicRequest::Latest => self.latest(),
+            ComicRequest::Random => self.random(),
+            ComicRequest::Date(date) => self.by_date(date),
+            ComicRequest::YearsAgo(years) => Box::pin(async move {
+                let date = years_before(today, years)
+                    .ok_or(ComicError::NoHistory { name: self.name() })?;
+                self.by_date(date).await
+            }),
+            ComicRequest::OnThisDay => Box::pin(async move {
+                let archive = self.archive().await?;
+                let mut dates = (1..)
+                    .map_while(|years| years_before(today, years))
+                    .take_while(|date| date >= archive.start())
+                    .filter(|date| archive.contains(date))
+                    .collect::<Vec<_>>();
+
+                dates.shuffle(&mut rand::thread_rng());
+                fetch_first_published(self, dates).await
+            }),
+        }
+    }
+}
+
+/// Fetches the strip of the first date that has one, as not every day has a strip.
+async fn fetch_first_published<S: ComicSource + ?Sized>(
+    source: &S,
+    dates: Vec<NaiveDate>,
+) -> anyhow::Result<Comic> {
+    for date in dates {
+        match source.by_date(date).await {
+            Err(err)
+                if matches!(
+                    err.downcast_ref::<ComicError>(),
+                    Some(ComicError::NotPublished { .. })
+                ) =>
+            {
+                continue
+            }
+            result => return result,
+        }
+    }
+
+    Err(ComicError::NoHistory {
+        name: source.name(),
+    }
+    .into())
+}
+
+/// Fails with `ComicError::OutsideArchive` unless the date is in the archive.
+fn check_archive(
+    name: &'static str,
+    date: NaiveDate,
+    archive: RangeInclusive<NaiveDate>,
+) -> Result<(), ComicError> {
+    if archive.contains(&date) {
+        Ok(())
+    } else {
+        Err(ComicError::OutsideArchive {
+            name,
+            date,
+            archive,
+        })
+    }
+}
+
+/// Comics posted by a comics subscription when the chat has not chosen its own.
+pub const DEFAULT_SUBSCRIPTION_COMICS: &[&str] = &["fingerpori", "garfield"];
+
+/// How comic images are given to Telegram. If it fails, the other one is tried.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ComicUpload {
+    /// Telegram fetches the image from its URL.
+    Url,
+    /// The image is downloaded and uploaded to Telegram.
+    Download,
+}
+
+impl FromStr for ComicUpload {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "url" => Ok(ComicUpload::Url),
+            "download" => Ok(ComicUpload::Download),
+            _ => Err(anyhow::anyhow!("Invalid comic upload: {}", s)),
+        }
+    }
+}
+
+impl ComicUpload {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ComicUpload::Url => "url",
+            ComicUpload::Download => "download",
+        }
+    }
+
+    /// This upload followed by the fallback.
+    pub fn with_fallback(self) -> [ComicUpload; 2] {
+        match self {
+            ComicUpload::Url => [ComicUpload::Url, ComicUpload::Download],
+            ComicUpload::Download => [ComicUpload::Download, ComicUpload::Url],
+        }
+    }
+}
+
+pub struct ComicRegistry {
+    sources: Vec<Box<dyn ComicSource>>,
+    http: Arc<dyn HttpClient>,
+    upload: ComicUpload,
+}
+
+impl ComicRegistry {
+    pub fn new(upload: ComicUpload) -> Self {
+        Self::with_http(Arc::new(ReqwestClient::new()), upload)
+    }
+
+    /// Creates the registry with the sources fetching pages through the given client.
+    pub fn with_http(http: Arc<dyn HttpClient>, upload: ComicUpload) -> Self {
+        Self {
+            sources: vec![
+                Box::new(HsLane::<Fingerpori>::new(http.clone())),
+                Box::new(HsLane::<Fokit>::new(http.clone())),
+                Box::new(GoComics::new(
+                    http.clone(),
+                    "garfield",
+                    "Garfield",
+                    "garfield",
+                    NaiveDate::from_ymd(1978, 6, 19),
+                )),
+            ],
+            http,
+            upload,
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&dyn ComicSource> {
+        self.iter()
+            .find(|source| source.id().eq_ignore_ascii_case(id))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn ComicSource> {
+        self.sources.iter().map(|source| source.as_ref())
+    }
+
+    pub fn upload(&self) -> ComicUpload {
+        self.upload
+    }
+
+    pub async fn download_image(&self, image_url: &Url) -> anyhow::Result<Vec<u8>> {
+        self.http.get_bytes(image_url.as_str()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_requests() {
+        assert_eq!(ComicRequest::from_str("").ok(), Some(ComicRequest::Latest));
+        assert_eq!(
+            ComicRequest::from_str("1988-06-19").ok(),
+            Some(ComicRequest::Date(NaiveDate::from_ymd(1988, 6, 19)))
+        );
+        assert_eq!(
+            ComicRequest::from_str("10v").ok(),
+            Some(ComicRequest::YearsAgo(10))
+        );
+        assert_eq!(
+            ComicRequest::from_str("3y").ok(),
+            Some(ComicRequest::YearsAgo(3))
+        );
+        assert_eq!(
+            ComicRequest::from_str("historia").ok(),
+            Some(ComicRequest::OnThisDay)
+        );
+        assert!(ComicRequest::from_str("0v").is_err());
+        assert!(ComicRequest::from_str("eilen").is_err());
+    }
+
+    #[test]
+    fn years_before_leap_day() {
+        let date = NaiveDate::from_ymd(2020, 2, 29);
+        assert_eq!(
+            years_before(date, 4),
+            Some(NaiveDate::from_ymd(2016, 2, 29))
+        );
+        assert_eq!(
+            years_before(date, 1),
+            Some(NaiveDate::from_ymd(2019, 2, 28))
+        );
+    }
+}
@@ -1,12 +1,22 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::Context;
+use dashmap::DashMap;
 use teloxide::{prelude::*, utils::command::BotCommands, RequestError};
 use thiserror::Error;
 
 use crate::{
-    autoreplies::AutoreplySetMap, chat_config::ChatConfigModel, db::DatabaseRef,
-    google::GoogleCalendarClientFactory, handlers, Command,
+    autoreplies::AutoreplySetMap,
+    chat_config::ChatConfigModel,
+    comics::{ComicRegistry, ComicRequest},
+    db::DatabaseRef,
+    google::GoogleCalendarClientFactory,
+    handlers,
+    scheduler::SchedulerWakeup,
+    Command,
 };
 
 #[derive(Debug, Error)]
@@ -56,6 +66,164 @@ pub fn succeed_with_message(message: impl Into<String>) -> HandlerResult {
     Ok(HandlerSuccess::Message(message.into()))
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Permission {
+    Public,
+    ChatAdmin,
+    BotOwner,
+}
+
+impl Permission {
+    pub fn required_for(command: &Command) -> Self {
+        match command {
+            Command::GetExcuse
+            | Command::Help
+            | Command::Fingerpori(_)
+            | Command::Randompori
+            | Command::Fokit(_)
+            | Command::RandomFokit
+            | Command::Lasaga(_)
+            | Command::RandomLasaga
+            | Command::Comic(_)
+            | Command::Comics
+            | Command::TestMessage(_)
+            | Command::ListMessages
+            | Command::ShowMessage(_)
+            | Command::Config
+            | Command::Subscriptions
+            | Command::Events => Permission::Public,
+            Command::Subscribe(_)
+            | Command::Unsubscribe(_)
+            | Command::SetSubscriptionCatchUp { .. }
+            | Command::SetSubscriptionComics(_)
+            | Command::AddMessage(_)
+            | Command::AddTemplate(_)
+            | Command::AddResponse(_)
+            | Command::RemoveResponse(_)
+            | Command::DeleteMessage(_)
+            | Command::RenameMessage(_)
+            | Command::SetMessageChance(_)
+            | Command::SetMessageCooldown(_)
+            | Command::EnableMessage(_)
+            | Command::DisableMessage(_)
+            | Command::SetAutoreplyChance(_)
+            | Command::Set(_)
+            | Command::ConnectGoogleCalendar(_)
+            | Command::DisconnectGoogleCalendar => Permission::ChatAdmin,
+            // Authorizations go through the bot owner's Google app
+            Command::StartGoogleAuth | Command::FinishGoogleAuth { .. } => Permission::BotOwner,
+        }
+    }
+
+    pub fn denied_message(&self) -> &'static str {
+        match self {
+            Permission::Public => "🤔",
+            Permission::ChatAdmin => "🚫 Vain kanavan ylläpitäjät voivat käyttää tätä komentoa.",
+            Permission::BotOwner => "🚫 Vain botin omistaja voi käyttää tätä komentoa.",
+        }
+    }
+}
+
+/// Services used by only a few commands, bundled because dptree endpoints can take at most
+/// nine arguments.
+#[derive(Clone)]
+pub struct CommandServices {
+    pub scheduler_wakeup: SchedulerWakeup,
+    pub comic_registry: Arc<ComicRegistry>,
+}
+
+const ADMIN_CACHE_DURATION: Duration = Duration::from_secs(10 * 60);
+
+pub struct PermissionChecker {
+    bot_owner_id: Option<UserId>,
+    admin_cache: DashMap<(ChatId, UserId), (bool, Instant)>,
+}
+
+impl PermissionChecker {
+    pub fn new(bot_owner_id: Option<UserId>) -> Self {
+        Self {
+            bot_owner_id,
+            admin_cache: DashMap::new(),
+        }
+    }
+
+    /// Checks whether the sender of the message is allowed to run the command.
+    pub async fn is_allowed(
+        &self,
+        bot: &AutoSend<Bot>,
+        message: &Message,
+        command: &Command,
+    ) -> anyhow::Result<bool> {
+        let required_permission = Permission::required_for(command);
+
+        if required_permission == Permission::Public {
+            return Ok(true);
+        }
+
+        let permission = self
+            .get_permission(bot, message)
+            .await
+            .context("Failed to check permissions")?;
+
+        Ok(permission >= required_permission)
+    }
+
+    async fn get_permission(
+        &self,
+        bot: &AutoSend<Bot>,
+        message: &Message,
+    ) -> anyhow::Result<Permission> {
+        let chat = &message.chat;
+
+        // Anonymous group admins send messages on behalf of the group itself.
+        if message.sender_chat().map(|sender_chat| sender_chat.id) == Some(chat.id) {
+            return Ok(Permission::ChatAdmin);
+        }
+
+        let user_id = match message.from() {
+            Some(user) => user.id,
+            None => return Ok(Permission::Public),
+        };
+
+        if Some(user_id) == self.bot_owner_id {
+            return Ok(Permission::BotOwner);
+        }
+
+        if chat.is_private() || self.is_chat_admin(bot, chat.id, user_id).await? {
+            Ok(Permission::ChatAdmin)
+        } else {
+            Ok(Permission::Public)
+        }
+    }
+
+    async fn is_chat_admin(
+        &self,
+        bot: &AutoSend<Bot>,
+        chat_id: ChatId,
+        user_id: UserId,
+    ) -> anyhow::Result<bool> {
+        if let Some(entry) = self.admin_cache.get(&(chat_id, user_id)) {
+            let (is_admin, checked_at) = *entry;
+
+            if checked_at.elapsed() < ADMIN_CACHE_DURATION {
+                return Ok(is_admin);
+            }
+        }
+
+        let member = bot
+            .get_chat_member(chat_id, user_id)
+            .await
+            .context("Failed to get chat member")?;
+        let is_admin = member.kind.is_privileged();
+
+        self.admin_cache
+            .insert((chat_id, user_id), (is_admin, Instant::now()));
+
+        Ok(is_admin)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_command(
     bot: AutoSend<Bot>,
     message: Message,
@@ -64,45 +232,207 @@ pub async fn handle_command(
     autoreply_set_map: AutoreplySetMap,
     chat_config_map: Arc<ChatConfigModel>,
     google_calendar_client_factory: GoogleCalendarClientFactory,
+    permission_checker: Arc<PermissionChecker>,
+    services: CommandServices,
 ) -> anyhow::Result<()> {
     let chat_id = message.chat.id;
 
+    if !permission_checker
+        .is_allowed(&bot, &message, &command)
+        .await?
+    {
+        bot.send_message(chat_id, Permission::required_for(&command).denied_message())
+            .await?;
+        return Ok(());
+    }
+
     let result = match command {
         Command::GetExcuse => handlers::handle_get_excuse()
             .await
             .handler_context("handle_get_excuse"),
         Command::Help => send_help(&bot, &message).await.handler_context("send_help"),
-        Command::Fingerpori => handlers::handle_fingerpori(&bot, chat_id)
-            .await
-            .handler_context("handle_fingerpori"),
-        Command::Randompori => handlers::handle_randompori(&bot, chat_id)
-            .await
-            .handler_context("handle_randompori"),
-        Command::Fokit => handlers::handle_fokit(&bot, chat_id)
-            .await
-            .handler_context("handle_fokit"),
-        Command::RandomFokit => handlers::handle_random_fokit(&bot, chat_id)
-            .await
-            .handler_context("handle_random_fokit"),
-        Command::Lasaga => handlers::handle_lasaga(&bot, chat_id)
-            .await
-            .handler_context("handle_lasaga"),
-        Command::RandomLasaga => handlers::handle_random_lasaga(&bot, chat_id)
+        Command::Fingerpori(args) => handlers::handle_comic_args(
+            &bot,
+            chat_id,
+            &db,
+            &chat_config_map,
+            &services.comic_registry,
+            "fingerpori",
+            &args,
+        )
+        .await
+        .handler_context("handle_fingerpori"),
+        Command::Randompori => handlers::handle_comic_request(
+            &bot,
+            chat_id,
+            &db,
+            &chat_config_map,
+            &services.comic_registry,
+            "fingerpori",
+            ComicRequest::Random,
+        )
+        .await
+        .handler_context("handle_randompori"),
+        Command::Fokit(args) => handlers::handle_comic_args(
+            &bot,
+            chat_id,
+            &db,
+            &chat_config_map,
+            &services.comic_registry,
+            "fokit",
+            &args,
+        )
+        .await
+        .handler_context("handle_fokit"),
+        Command::RandomFokit => handlers::handle_comic_request(
+            &bot,
+            chat_id,
+            &db,
+            &chat_config_map,
+            &services.comic_registry,
+            "fokit",
+            ComicRequest::Random,
+        )
+        .await
+        .handler_context("handle_random_fokit"),
+        Command::Lasaga(args) => handlers::handle_comic_args(
+            &bot,
+            chat_id,
+            &db,
+            &chat_config_map,
+            &services.comic_registry,
+            "garfield",
+            &args,
+        )
+        .await
+        .handler_context("handle_lasaga"),
+        Command::RandomLasaga => handlers::handle_comic_request(
+            &bot,
+            chat_id,
+            &db,
+            &chat_config_map,
+            &services.comic_registry,
+            "garfield",
+            ComicRequest::Random,
+        )
+        .await
+        .handler_context("handle_random_lasaga"),
+        Command::Comic(args) => handlers::handle_comic(
+            &bot,
+            chat_id,
+            &db,
+            &chat_config_map,
+            &services.comic_registry,
+            &args,
+        )
+        .await
+        .handler_context("handle_comic"),
+        Command::Comics => handlers::handle_list_comics(chat_id, db, &services.comic_registry)
             .await
-            .handler_context("handle_random_lasaga"),
-        Command::Subscribe { kind, time } => handlers::handle_subscribe(chat_id, db, &kind, &time)
+            .handler_context("handle_list_comics"),
+        Command::SetSubscriptionComics(args) => {
+            handlers::handle_set_subscription_comics(chat_id, db, &services.comic_registry, &args)
+                .await
+                .handler_context("handle_set_subscription_comics")
+        }
+        Command::Subscribe(args) => handlers::handle_subscribe(
+            chat_id,
+            db,
+            chat_config_map,
+            &services.scheduler_wakeup,
+            &args,
+        )
+        .await
+        .handler_context("handle_subscribe"),
+        Command::Subscriptions => handlers::handle_list_subscriptions(chat_id, db, chat_config_map)
             .await
-            .handler_context("handle_subscribe"),
+            .handler_context("handle_list_subscriptions"),
+        Command::Unsubscribe(id) => {
+            handlers::handle_unsubscribe(chat_id, db, &services.scheduler_wakeup, id)
+                .await
+                .handler_context("handle_unsubscribe")
+        }
+        Command::SetSubscriptionCatchUp { id, minutes } => {
+            handlers::handle_set_subscription_catch_up(chat_id, db, id, minutes)
+                .await
+                .handler_context("handle_set_subscription_catch_up")
+        }
         Command::AddMessage(args) => {
             handlers::handle_add_message(chat_id, db, autoreply_set_map, &args)
                 .await
                 .handler_context("handle_add_message")
         }
+        Command::AddTemplate(args) => {
+            handlers::handle_add_template(chat_id, db, autoreply_set_map, &args)
+                .await
+                .handler_context("handle_add_template")
+        }
+        Command::AddResponse(args) => {
+            handlers::handle_add_response(chat_id, db, autoreply_set_map, &args)
+                .await
+                .handler_context("handle_add_response")
+        }
+        Command::RemoveResponse(args) => {
+            handlers::handle_remove_response(chat_id, db, autoreply_set_map, &args)
+                .await
+                .handler_context("handle_remove_response")
+        }
+        Command::TestMessage(text) => {
+            handlers::handle_test_message(&message, autoreply_set_map, chat_config_map, &text)
+                .await
+                .handler_context("handle_test_message")
+        }
+        Command::ListMessages => handlers::handle_list_messages(chat_id, autoreply_set_map)
+            .await
+            .handler_context("handle_list_messages"),
+        Command::ShowMessage(args) => {
+            handlers::handle_show_message(chat_id, autoreply_set_map, &args)
+                .await
+                .handler_context("handle_show_message")
+        }
+        Command::DeleteMessage(args) => {
+            handlers::handle_delete_message(chat_id, db, autoreply_set_map, &args)
+                .await
+                .handler_context("handle_delete_message")
+        }
+        Command::RenameMessage(args) => {
+            handlers::handle_rename_message(chat_id, db, autoreply_set_map, &args)
+                .await
+                .handler_context("handle_rename_message")
+        }
+        Command::SetMessageChance(args) => {
+            handlers::handle_set_message_chance(chat_id, db, autoreply_set_map, &args)
+                .await
+                .handler_context("handle_set_message_chance")
+        }
+        Command::SetMessageCooldown(args) => {
+            handlers::handle_set_message_cooldown(chat_id, db, autoreply_set_map, &args)
+                .await
+                .handler_context("handle_set_message_cooldown")
+        }
+        Command::EnableMessage(args) => {
+            handlers::handle_set_message_enabled(chat_id, db, autoreply_set_map, &args, true)
+                .await
+                .handler_context("handle_set_message_enabled")
+        }
+        Command::DisableMessage(args) => {
+            handlers::handle_set_message_enabled(chat_id, db, autoreply_set_map, &args, false)
+                .await
+                .handler_context("handle_set_message_enabled")
+        }
         Command::SetAutoreplyChance(value) => {
             handlers::handle_set_autoreply_chance(chat_id, chat_config_map, value)
                 .await
                 .handler_context("handle_set_autoreply_chance")
         }
+        Command::Config => handlers::handle_show_config(chat_id, chat_config_map)
+            .await
+            .handler_context("handle_show_config"),
+        Command::Set(args) => {
+            handlers::handle_set(chat_id, chat_config_map, &services.scheduler_wakeup, &args)
+                .await
+                .handler_context("handle_set")
+        }
         Command::StartGoogleAuth => {
             handlers::handle_start_google_auth(message, google_calendar_client_factory.clone())
                 .await
@@ -132,12 +462,22 @@ pub async fn handle_command(
             &bot,
             message,
             db,
+            chat_config_map,
             google_calendar_client_factory.clone(),
         )
         .await
         .handler_context("print_calendar_events"),
     };
 
+    send_handler_result(&bot, chat_id, result).await
+}
+
+/// Sends the outcome of a handler to the chat: the success or error message, if any.
+pub async fn send_handler_result(
+    bot: &AutoSend<Bot>,
+    chat_id: ChatId,
+    result: HandlerResult,
+) -> anyhow::Result<()> {
     match result {
         Ok(HandlerSuccess::Finished) => {}
         Ok(HandlerSuccess::Message(message)) => {
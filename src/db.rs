@@ -2,19 +2,25 @@ use std::{
     collections::{HashMap, VecDeque},
     str::FromStr,
     sync::Arc,
+    time::Duration,
 };
 
 use anyhow::Context;
-use chrono::{DateTime, Local, NaiveTime};
-use regex::Regex;
+use chrono::{NaiveDate, NaiveDateTime};
+use chrono_tz::Tz;
+use reqwest::Url;
 use rusqlite::Connection;
 use teloxide::types::{ChatId, UserId};
 use tokio::sync::Mutex;
 
 use crate::{
     autoreplies::{Autoreply, ChatStickerCache, StickerEntry, StickersForEmoji},
-    chat_config::ChatConfig,
-    subscriptions::{Subscription, SubscriptionType, TIME_FORMAT},
+    autoreply_pattern::{AutoreplyPattern, MatchMode},
+    chat_config::{ChatConfig, DateLocale},
+    comics::Comic,
+    quiet_hours::QuietHours,
+    schedule::Schedule,
+    subscriptions::{Subscription, SubscriptionType},
 };
 
 #[derive(Debug)]
@@ -23,13 +29,32 @@ pub struct Database(Connection);
 pub struct DatabaseRef(Arc<Mutex<Database>>);
 
 const SQL_TIME_FORMAT: &str = "%F %T";
+const SQL_DATE_FORMAT: &str = "%F";
+
+/// Schema changes applied on top of `create_db.sql`, in order.
+/// The number of applied migrations is tracked in `PRAGMA user_version`.
+const MIGRATIONS: &[&str] = &[
+    include_str!("sql/migrations/001_autoreply_options.sql"),
+    include_str!("sql/migrations/002_autoreply_response_pools.sql"),
+    include_str!("sql/migrations/003_autoreply_match_modes.sql"),
+    include_str!("sql/migrations/004_reply_rate_limits.sql"),
+    include_str!("sql/migrations/005_quiet_hours.sql"),
+    include_str!("sql/migrations/006_chat_timezones.sql"),
+    include_str!("sql/migrations/007_subscription_ids.sql"),
+    include_str!("sql/migrations/008_subscription_retries.sql"),
+    include_str!("sql/migrations/009_chat_comics.sql"),
+    include_str!("sql/migrations/010_comic_cache.sql"),
+    include_str!("sql/migrations/011_comic_history_index.sql"),
+];
 
 pub fn open_and_prepare_db() -> anyhow::Result<DatabaseRef> {
-    let connection = Connection::open("haloo.db3").context("Failed to open SQLite database")?;
+    let mut connection = Connection::open("haloo.db3").context("Failed to open SQLite database")?;
 
     connection
         .execute_batch(include_str!("sql/create_db.sql"))
-        .context("Failed to run database migrations")?;
+        .context("Failed to create database tables")?;
+
+    run_migrations(&mut connection).context("Failed to run database migrations")?;
 
     log::info!("Database prepared.");
 
@@ -39,18 +64,50 @@ pub fn open_and_prepare_db() -> anyhow::Result<DatabaseRef> {
     Ok(db_ref)
 }
 
+fn run_migrations(connection: &mut Connection) -> anyhow::Result<()> {
+    let version: usize = connection.query_row("PRAGMA user_version", (), |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.transaction()?;
+        transaction
+            .execute_batch(migration)
+            .with_context(|| format!("Failed to run migration {}", index + 1))?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+        transaction.commit()?;
+
+        log::info!("Applied database migration {}", index + 1);
+    }
+
+    Ok(())
+}
+
 impl DatabaseRef {
-    pub async fn set_autoreply_chance(&self, chat_id: ChatId, chance: f64) -> anyhow::Result<()> {
+    pub async fn save_chat_config(&self, config: &ChatConfig) -> anyhow::Result<()> {
         let db = self.0.lock().await;
 
         db.0.prepare(
             "
-            INSERT INTO chat_settings(chat_id, autoreply_chance) VALUES (?1, ?2)
-            ON CONFLICT DO UPDATE SET autoreply_chance = ?2
+            INSERT INTO chat_settings(
+              chat_id, autoreply_chance, sticker_lru_size, reply_burst, replies_per_hour,
+              quiet_hours, timezone, locale
+            )
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            ON CONFLICT DO UPDATE
+            SET autoreply_chance = ?2, sticker_lru_size = ?3, reply_burst = ?4, replies_per_hour = ?5,
+              quiet_hours = ?6, timezone = ?7, locale = ?8
             ",
         )?
-        .execute((chat_id.0, chance))
-        .context("Failed to update autoreply chance")?;
+        .execute((
+            config.chat_id.0,
+            config.autoreply_chance,
+            config.sticker_lru_size,
+            config.reply_burst,
+            config.replies_per_hour,
+            config.quiet_hours.to_string(),
+            config.timezone.name(),
+            config.locale.as_str(),
+        ))
+        .context("Failed to update chat settings")?;
 
         Ok(())
     }
@@ -59,18 +116,34 @@ impl DatabaseRef {
         let db = self.0.lock().await;
 
         let mut statement = db.0.prepare(
-            "SELECT autoreply_chance, sticker_lru_size FROM chat_settings WHERE chat_id = ?1",
+            "
+            SELECT autoreply_chance, sticker_lru_size, reply_burst, replies_per_hour, quiet_hours,
+              timezone, locale
+            FROM chat_settings
+            WHERE chat_id = ?1
+            ",
         )?;
 
         let mut maybe_row =
             statement.query_and_then::<_, anyhow::Error, _, _>((chat_id.0,), |row| {
                 let autoreply_chance = row.get::<_, f64>(0)?;
                 let sticker_lru_size = row.get::<_, u32>(1)?;
+                let reply_burst = row.get::<_, u32>(2)?;
+                let replies_per_hour = row.get::<_, u32>(3)?;
+                let quiet_hours = QuietHours::from_str(&row.get::<_, String>(4)?)?;
+                let timezone =
+                    Tz::from_str(&row.get::<_, String>(5)?).map_err(anyhow::Error::msg)?;
+                let locale = DateLocale::from_str(&row.get::<_, String>(6)?)?;
 
                 Ok(ChatConfig {
                     chat_id,
                     autoreply_chance,
                     sticker_lru_size,
+                    reply_burst,
+                    replies_per_hour,
+                    quiet_hours,
+                    timezone,
+                    locale,
                 })
             })?;
 
@@ -80,107 +153,326 @@ impl DatabaseRef {
         }
     }
 
-    pub async fn get_pending_subscriptions(
+    pub async fn get_subscriptions(&self) -> anyhow::Result<Vec<Subscription>> {
+        let db = self.0.lock().await;
+
+        let mut statement = db.0.prepare(
+            "
+            SELECT id, chat_id, subscription_type, schedule, last_updated, catch_up_minutes,
+              retry_count, retry_at
+            FROM subscriptions
+          ",
+        )?;
+
+        read_subscriptions(&mut statement, ())
+    }
+
+    pub async fn get_chat_subscriptions(
         &self,
-        now: DateTime<Local>,
+        chat_id: ChatId,
     ) -> anyhow::Result<Vec<Subscription>> {
         let db = self.0.lock().await;
 
-        let formatted_time = now.format(SQL_TIME_FORMAT).to_string();
-
         let mut statement = db.0.prepare(
             "
-            SELECT chat_id, subscription_type, time
+            SELECT id, chat_id, subscription_type, schedule, last_updated, catch_up_minutes,
+              retry_count, retry_at
             FROM subscriptions
-            WHERE
-              ((last_updated IS NULL) OR (date(last_updated) < date(?1)))
-              AND time(subscriptions.time) <= time(?1)
+            WHERE chat_id = ?1
+            ORDER BY id
           ",
         )?;
 
-        let rows: Vec<Subscription> = statement
-            .query((formatted_time,))
+        read_subscriptions(&mut statement, [chat_id.0])
+    }
+
+    pub async fn mark_subscription_updated(
+        &self,
+        subscription: &Subscription,
+        now: NaiveDateTime,
+    ) -> anyhow::Result<()> {
+        let db = self.0.lock().await;
+
+        let formatted_time = now.format(SQL_TIME_FORMAT).to_string();
+
+        db.0.execute(
+            "
+                UPDATE subscriptions
+                SET last_updated = ?2, retry_count = 0, retry_at = NULL
+                WHERE id = ?1
+            ",
+            (subscription.id, formatted_time),
+        )
+        .context("Failed to update subscription timestamp")?;
+
+        Ok(())
+    }
+
+    pub async fn mark_subscription_failed(
+        &self,
+        subscription: &Subscription,
+        retry_count: u32,
+        retry_at: NaiveDateTime,
+    ) -> anyhow::Result<()> {
+        let db = self.0.lock().await;
+
+        let formatted_time = retry_at.format(SQL_TIME_FORMAT).to_string();
+
+        db.0.execute(
+            "
+                UPDATE subscriptions
+                SET retry_count = ?2, retry_at = ?3
+                WHERE id = ?1
+            ",
+            (subscription.id, retry_count, formatted_time),
+        )
+        .context("Failed to update subscription retry")?;
+
+        Ok(())
+    }
+
+    /// Returns false if the chat has no subscription with the given id.
+    pub async fn set_subscription_catch_up(
+        &self,
+        chat_id: ChatId,
+        id: i64,
+        catch_up_minutes: u32,
+    ) -> anyhow::Result<bool> {
+        let db = self.0.lock().await;
+
+        let updated = db.0.execute(
+            "UPDATE subscriptions SET catch_up_minutes = ?3 WHERE chat_id = ?1 AND id = ?2",
+            (chat_id.0, id, catch_up_minutes),
+        )?;
+
+        Ok(updated > 0)
+    }
+
+    /// Adds a new subscription and returns its id.
+    pub async fn add_subscription(
+        &self,
+        chat_id: ChatId,
+        kind: SubscriptionType,
+        schedule: &Schedule,
+        now: NaiveDateTime,
+    ) -> anyhow::Result<i64> {
+        let db = self.0.lock().await;
+
+        // The subscription counts as handled when it is added, so that earlier runs are not overdue
+        db.0.execute(
+            "
+            INSERT INTO subscriptions (chat_id, subscription_type, schedule, last_updated) VALUES (?1, ?2, ?3, ?4)
+        ",
+            (
+                chat_id.0,
+                kind.as_str(),
+                schedule.to_string(),
+                now.format(SQL_TIME_FORMAT).to_string(),
+            ),
+        )?;
+
+        Ok(db.0.last_insert_rowid())
+    }
+
+    /// Returns false if the chat has no subscription with the given id.
+    pub async fn remove_subscription(&self, chat_id: ChatId, id: i64) -> anyhow::Result<bool> {
+        let db = self.0.lock().await;
+
+        let removed = db.0.execute(
+            "DELETE FROM subscriptions WHERE chat_id = ?1 AND id = ?2",
+            (chat_id.0, id),
+        )?;
+
+        Ok(removed > 0)
+    }
+
+    /// Returns the comics the chat has chosen for its comics subscriptions, in order.
+    pub async fn get_chat_comics(&self, chat_id: ChatId) -> anyhow::Result<Vec<String>> {
+        let db = self.0.lock().await;
+
+        let mut statement = db.0.prepare(
+            "
+            SELECT comic_id
+            FROM chat_comics
+            WHERE chat_id = ?1
+            ORDER BY position
+        ",
+        )?;
+
+        let comic_ids = statement
+            .query((chat_id.0,))
             .context("Failed to query database")?
             .mapped(|row| {
-                let chat_id: i64 = row.get(0)?;
-                let subscription_type: String = row.get(1)?;
-                let time: String = row.get(2)?;
+                let comic_id: String = row.get(0)?;
 
-                Ok((chat_id, subscription_type, time))
+                Ok(comic_id)
             })
             .filter_map(|row| match row {
                 Err(err) => {
-                    log::error!("Failed to read subscription row: {:?}", err);
+                    log::error!("Failed to read chat comic row: {:?}", err);
                     None
                 }
                 Ok(row) => Some(row),
             })
-            .map(
-                |(chat_id, subscription_type, time)| -> anyhow::Result<Subscription> {
-                    let chat_id = ChatId(chat_id);
-                    let subscription_type = SubscriptionType::from_str(&subscription_type)?;
-                    let time = NaiveTime::parse_from_str(&time, TIME_FORMAT)
-                        .with_context(|| format!("Invalid time: {}", time))?;
-                    Ok(Subscription {
-                        chat_id,
-                        kind: subscription_type,
-                        time,
-                    })
-                },
-            )
-            .filter_map(|maybe_row| match maybe_row {
+            .collect();
+
+        Ok(comic_ids)
+    }
+
+    /// Replaces the chat's comic selection. An empty selection restores the defaults.
+    pub async fn set_chat_comics(&self, chat_id: ChatId, comic_ids: &[&str]) -> anyhow::Result<()> {
+        let mut db = self.0.lock().await;
+        let transaction = db.0.transaction()?;
+
+        transaction.execute("DELETE FROM chat_comics WHERE chat_id = ?1", (chat_id.0,))?;
+
+        for (position, comic_id) in comic_ids.iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO chat_comics (chat_id, comic_id, position) VALUES (?1, ?2, ?3)",
+                (chat_id.0, comic_id, position),
+            )?;
+        }
+
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    pub async fn get_cached_comic(
+        &self,
+        comic_id: &str,
+        date: NaiveDate,
+    ) -> anyhow::Result<Option<Comic>> {
+        let db = self.0.lock().await;
+
+        let mut statement = db.0.prepare(
+            "
+            SELECT image_url, file_id
+            FROM comic_cache
+            WHERE comic_id = ?1 AND date = ?2
+        ",
+        )?;
+
+        let maybe_row = statement
+            .query((comic_id, date.format(SQL_DATE_FORMAT).to_string()))
+            .context("Failed to query database")?
+            .mapped(|row| {
+                let image_url: String = row.get(0)?;
+                let file_id: Option<String> = row.get(1)?;
+
+                Ok((image_url, file_id))
+            })
+            .find_map(|row| match row {
                 Err(err) => {
-                    log::error!("Failed to parse subscription row: {:?}", err);
+                    log::error!("Failed to read comic cache row: {:?}", err);
                     None
                 }
                 Ok(row) => Some(row),
-            })
-            .collect();
+            });
 
-        Ok(rows)
+        let comic = match maybe_row {
+            Some((image_url, file_id)) => Some(Comic {
+                image_url: Url::parse(&image_url)
+                    .with_context(|| format!("Invalid cached comic URL: {}", image_url))?,
+                date: Some(date),
+                file_id,
+            }),
+            None => None,
+        };
+
+        Ok(comic)
     }
 
-    pub async fn mark_subscription_updated(
+    pub async fn cache_comic(
         &self,
-        subscription: &Subscription,
-        now: DateTime<Local>,
+        comic_id: &str,
+        date: NaiveDate,
+        image_url: &str,
+        file_id: Option<&str>,
     ) -> anyhow::Result<()> {
         let db = self.0.lock().await;
 
-        let chat_id = subscription.chat_id.0;
-        let subscription_type = subscription.kind.as_str();
+        db.0.execute(
+            "
+            INSERT INTO comic_cache (comic_id, date, image_url, file_id)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (comic_id, date) DO UPDATE
+            SET image_url = ?3, file_id = ?4
+        ",
+            (
+                comic_id,
+                date.format(SQL_DATE_FORMAT).to_string(),
+                image_url,
+                file_id,
+            ),
+        )
+        .context("Failed to cache comic")?;
 
-        let formatted_time = now.format(SQL_TIME_FORMAT).to_string();
+        Ok(())
+    }
+
+    /// Records the strip as shown in the chat and forgets the chat's history from before `forget_before`.
+    pub async fn add_comic_history(
+        &self,
+        chat_id: ChatId,
+        comic_id: &str,
+        image_url: &str,
+        now: NaiveDateTime,
+        forget_before: NaiveDateTime,
+    ) -> anyhow::Result<()> {
+        let db = self.0.lock().await;
+
+        db.0.execute(
+            "DELETE FROM comic_history WHERE chat_id = ?1 AND shown_at < ?2",
+            (chat_id.0, forget_before.format(SQL_TIME_FORMAT).to_string()),
+        )
+        .context("Failed to prune comic history")?;
 
         db.0.execute(
             "
-                UPDATE subscriptions
-                SET last_updated = ?3
-                WHERE chat_id = ?1 AND subscription_type = ?2
-            ",
-            (chat_id, subscription_type, formatted_time),
+            INSERT INTO comic_history (chat_id, comic_id, image_url, shown_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (chat_id, comic_id, image_url) DO UPDATE
+            SET shown_at = ?4
+        ",
+            (
+                chat_id.0,
+                comic_id,
+                image_url,
+                now.format(SQL_TIME_FORMAT).to_string(),
+            ),
         )
-        .context("Failed to update subscription timestamp")?;
+        .context("Failed to add comic history")?;
 
         Ok(())
     }
 
-    pub async fn add_subscription(&self, subscription: &Subscription) -> anyhow::Result<()> {
+    /// Whether the strip has been shown in the chat after `since`.
+    pub async fn was_comic_shown_since(
+        &self,
+        chat_id: ChatId,
+        comic_id: &str,
+        image_url: &str,
+        since: NaiveDateTime,
+    ) -> anyhow::Result<bool> {
         let db = self.0.lock().await;
 
-        db.0.execute(
+        let count: u32 = db.0.query_row(
             "
-            INSERT INTO subscriptions (chat_id, subscription_type, time) VALUES (?1, ?2, ?3)
-            ON CONFLICT (chat_id, subscription_type) DO UPDATE SET time = ?3
+            SELECT COUNT(*)
+            FROM comic_history
+            WHERE chat_id = ?1 AND comic_id = ?2 AND image_url = ?3 AND shown_at > ?4
         ",
             (
-                subscription.chat_id.0,
-                subscription.kind.as_str(),
-                subscription.time.format(TIME_FORMAT).to_string().as_str(),
+                chat_id.0,
+                comic_id,
+                image_url,
+                since.format(SQL_TIME_FORMAT).to_string(),
             ),
+            |row| row.get(0),
         )?;
 
-        Ok(())
+        Ok(count > 0)
     }
 
     pub async fn add_autoreply(&self, autoreply: &Autoreply) -> anyhow::Result<()> {
@@ -188,26 +480,88 @@ impl DatabaseRef {
 
         db.0.execute(
             "
-            INSERT INTO autoreplies (chat_id, name, pattern_regex, response_json) VALUES (?1, ?2, ?3, ?4)
-            ON CONFLICT (chat_id, name) DO UPDATE SET pattern_regex = ?3, response_json = ?4
+            INSERT INTO autoreplies (chat_id, name, pattern_regex, match_mode, response_json, chance, cooldown_seconds, enabled)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            ON CONFLICT (chat_id, name) DO UPDATE
+            SET pattern_regex = ?3, match_mode = ?4, response_json = ?5, chance = ?6, cooldown_seconds = ?7, enabled = ?8
         ",
             (
                 autoreply.chat_id.0,
                 &autoreply.name,
-                autoreply.pattern_regex.as_str(),
-                serde_json::to_string(&autoreply.response).unwrap(),
+                &autoreply.pattern.pattern,
+                autoreply.pattern.match_mode.as_str(),
+                serde_json::to_string(&autoreply.responses).unwrap(),
+                autoreply.chance,
+                autoreply.cooldown.map(|cooldown| cooldown.as_secs()),
+                autoreply.enabled,
             ),
         )?;
 
         Ok(())
     }
 
+    pub async fn update_autoreply(&self, autoreply: &Autoreply) -> anyhow::Result<bool> {
+        let db = self.0.lock().await;
+
+        let updated_rows = db.0.execute(
+            "
+            UPDATE autoreplies
+            SET response_json = ?3, chance = ?4, cooldown_seconds = ?5, enabled = ?6
+            WHERE chat_id = ?1 AND name = ?2
+        ",
+            (
+                autoreply.chat_id.0,
+                &autoreply.name,
+                serde_json::to_string(&autoreply.responses).unwrap(),
+                autoreply.chance,
+                autoreply.cooldown.map(|cooldown| cooldown.as_secs()),
+                autoreply.enabled,
+            ),
+        )?;
+
+        Ok(updated_rows > 0)
+    }
+
+    pub async fn remove_autoreply(&self, chat_id: ChatId, name: &str) -> anyhow::Result<bool> {
+        let db = self.0.lock().await;
+
+        let removed_rows = db.0.execute(
+            "
+            DELETE FROM autoreplies
+            WHERE chat_id = ?1 AND name = ?2
+        ",
+            (chat_id.0, name),
+        )?;
+
+        Ok(removed_rows > 0)
+    }
+
+    pub async fn rename_autoreply(
+        &self,
+        chat_id: ChatId,
+        old_name: &str,
+        new_name: &str,
+    ) -> anyhow::Result<bool> {
+        let db = self.0.lock().await;
+
+        let updated_rows = db.0.execute(
+            "
+            UPDATE autoreplies
+            SET name = ?3
+            WHERE chat_id = ?1 AND name = ?2
+        ",
+            (chat_id.0, old_name, new_name),
+        )?;
+
+        Ok(updated_rows > 0)
+    }
+
     pub async fn get_autoreplies(&self) -> anyhow::Result<Vec<Autoreply>> {
         let db = self.0.lock().await;
 
         let mut statement = db.0.prepare(
             "
-            SELECT chat_id, name, pattern_regex, response_json
+            SELECT chat_id, name, pattern_regex, match_mode, response_json, chance, cooldown_seconds, enabled
             FROM autoreplies
         ",
         )?;
@@ -219,9 +573,22 @@ impl DatabaseRef {
                 let chat_id = row.get(0)?;
                 let name: String = row.get(1)?;
                 let pattern_regex: String = row.get(2)?;
-                let response_json: String = row.get(3)?;
+                let match_mode: String = row.get(3)?;
+                let response_json: String = row.get(4)?;
+                let chance: Option<f64> = row.get(5)?;
+                let cooldown_seconds: Option<u64> = row.get(6)?;
+                let enabled: bool = row.get(7)?;
 
-                Ok((chat_id, name, pattern_regex, response_json))
+                Ok((
+                    chat_id,
+                    name,
+                    pattern_regex,
+                    match_mode,
+                    response_json,
+                    chance,
+                    cooldown_seconds,
+                    enabled,
+                ))
             })
             .filter_map(|row| match row {
                 Err(err) => {
@@ -231,17 +598,32 @@ impl DatabaseRef {
                 Ok(row) => Some(row),
             })
             .map(
-                |(chat_id, name, pattern_regex, response_json)| -> anyhow::Result<Autoreply> {
+                |(
+                    chat_id,
+                    name,
+                    pattern_regex,
+                    match_mode,
+                    response_json,
+                    chance,
+                    cooldown_seconds,
+                    enabled,
+                )|
+                 -> anyhow::Result<Autoreply> {
                     let chat_id = ChatId(chat_id);
                     let name = name;
-                    let pattern_regex = Regex::new(&pattern_regex)?;
-                    let response = serde_json::from_str(&response_json)?;
+                    let match_mode = MatchMode::from_str(&match_mode)?;
+                    let pattern = AutoreplyPattern::new(match_mode, pattern_regex)?;
+                    let responses = serde_json::from_str(&response_json)?;
+                    let cooldown = cooldown_seconds.map(Duration::from_secs);
 
                     Ok(Autoreply {
                         chat_id,
                         name,
-                        pattern_regex,
-                        response,
+                        pattern,
+                        responses,
+                        chance,
+                        cooldown,
+                        enabled,
                     })
                 },
             )
@@ -461,4 +843,123 @@ impl DatabaseRef {
 
         Ok(maybe_row)
     }
+
+    /// Returns the connected calendar id together with the user who connected it.
+    pub async fn get_connected_calendar(
+        &self,
+        chat_id: ChatId,
+    ) -> anyhow::Result<Option<(UserId, String)>> {
+        let db = self.0.lock().await;
+
+        let mut statement = db.0.prepare(
+            "
+            SELECT user_id, calendar_id
+            FROM connected_calendars
+            WHERE chat_id = ?1
+        ",
+        )?;
+
+        let rows = statement
+            .query((chat_id.0,))
+            .context("Failed to query database")?;
+
+        let maybe_row = rows
+            .mapped(|row| {
+                let user_id: u64 = row.get(0)?;
+                let calendar_id: String = row.get(1)?;
+
+                Ok((UserId(user_id), calendar_id))
+            })
+            .find_map(|row| match row {
+                Err(err) => {
+                    log::error!("Failed to read connected calendar row: {:?}", err);
+                    None
+                }
+                Ok(row) => Some(row),
+            });
+
+        Ok(maybe_row)
+    }
+}
+
+fn read_subscriptions(
+    statement: &mut rusqlite::Statement,
+    params: impl rusqlite::Params,
+) -> anyhow::Result<Vec<Subscription>> {
+    let rows: Vec<Subscription> = statement
+        .query(params)
+        .context("Failed to query database")?
+        .mapped(|row| {
+            let id: i64 = row.get(0)?;
+            let chat_id: i64 = row.get(1)?;
+            let subscription_type: String = row.get(2)?;
+            let schedule: String = row.get(3)?;
+            let last_updated: Option<String> = row.get(4)?;
+            let catch_up_minutes: u32 = row.get(5)?;
+            let retry_count: u32 = row.get(6)?;
+            let retry_at: Option<String> = row.get(7)?;
+
+            Ok((
+                id,
+                chat_id,
+                subscription_type,
+                schedule,
+                last_updated,
+                catch_up_minutes,
+                retry_count,
+                retry_at,
+            ))
+        })
+        .filter_map(|row| match row {
+            Err(err) => {
+                log::error!("Failed to read subscription row: {:?}", err);
+                None
+            }
+            Ok(row) => Some(row),
+        })
+        .map(
+            |(
+                id,
+                chat_id,
+                subscription_type,
+                schedule,
+                last_updated,
+                catch_up_minutes,
+                retry_count,
+                retry_at,
+            )|
+             -> anyhow::Result<Subscription> {
+                let chat_id = ChatId(chat_id);
+                let subscription_type = SubscriptionType::from_str(&subscription_type)?;
+                let schedule = Schedule::from_str(&schedule)
+                    .with_context(|| format!("Invalid schedule: {}", schedule))?;
+                let last_updated = last_updated.as_deref().map(parse_sql_time).transpose()?;
+                let retry_at = retry_at.as_deref().map(parse_sql_time).transpose()?;
+                Ok(Subscription {
+                    id,
+                    chat_id,
+                    kind: subscription_type,
+                    schedule,
+                    last_updated,
+                    catch_up_minutes,
+                    retry_count,
+                    retry_at,
+                })
+            },
+        )
+        .filter_map(|maybe_row| match maybe_row {
+            Err(err) => {
+                log::error!("Failed to parse subscription row: {:?}", err);
+                None
+            }
+            Ok(row) => Some(row),
+        })
+        .collect();
+
+    Ok(rows)
+}
+
+fn parse_sql_time(time: &str) -> anyhow::Result<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(time, SQL_TIME_FORMAT)
+        .with_context(|| format!("Invalid timestamp: {}", time))
 }
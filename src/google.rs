@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use chrono::{DateTime, Duration, NaiveDate};
+use chrono_tz::Tz;
 use google_calendar::types::{Event, EventDateTime, OrderBy};
 use itertools::{Either, Itertools};
 use once_cell::sync::OnceCell;
@@ -88,10 +89,10 @@ enum SummaryEvent {
 }
 
 impl EventWithConfig {
-    pub fn as_summary_event(self, today: NaiveDate) -> Option<SummaryEvent> {
+    pub fn as_summary_event(self, today: NaiveDate, timezone: &Tz) -> Option<SummaryEvent> {
         let countdown_days = self.1.countdown_days.unwrap_or(0);
 
-        let event_date = self.0.get_start_date()?;
+        let event_date = self.0.get_start_date(timezone)?;
 
         if event_date == today {
             return Some(SummaryEvent::Today(self.0));
@@ -145,7 +146,7 @@ pub struct EventsSummary {
 pub async fn get_events_to_announce(
     client: &google_calendar::Client,
     calendar_id: &str,
-    now: DateTime<chrono::Local>,
+    now: DateTime<Tz>,
 ) -> anyhow::Result<EventsSummary> {
     let start_time = now;
     let end_time = start_time + Duration::days(FETCH_DAYS_IN_FUTURE);
@@ -173,7 +174,8 @@ pub async fn get_events_to_announce(
         )
         .await?;
 
-    let today = now.date().naive_local();
+    let timezone = now.timezone();
+    let today = now.date_naive();
 
     let events_with_config = events
         .into_iter()
@@ -181,7 +183,7 @@ pub async fn get_events_to_announce(
             let config = get_event_config(&event);
             EventWithConfig(event, config)
         })
-        .filter_map(|event| event.as_summary_event(today));
+        .filter_map(|event| event.as_summary_event(today, &timezone));
 
     let (today, upcoming): (Vec<Event>, Vec<UpcomingEvent>) =
         events_with_config.partition_map(|event| match event {
@@ -193,11 +195,12 @@ pub async fn get_events_to_announce(
 }
 
 pub trait EventExt {
-    fn get_start_date(&self) -> Option<NaiveDate>;
+    /// Returns the date the event starts on in the given timezone.
+    fn get_start_date(&self, timezone: &Tz) -> Option<NaiveDate>;
 }
 
 impl EventExt for Event {
-    fn get_start_date(&self) -> Option<NaiveDate> {
+    fn get_start_date(&self, timezone: &Tz) -> Option<NaiveDate> {
         match self.start {
             Some(EventDateTime {
                 date: Some(date), ..
@@ -205,7 +208,7 @@ impl EventExt for Event {
             Some(EventDateTime {
                 date_time: Some(start_time),
                 ..
-            }) => Some(start_time.date_naive()),
+            }) => Some(start_time.with_timezone(timezone).date_naive()),
             _ => None,
         }
     }
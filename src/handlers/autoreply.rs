@@ -1,9 +1,15 @@
-use regex::Regex;
+use std::{borrow::Cow, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use itertools::Itertools;
 use teloxide::types::{ChatId, Message};
 
 use crate::{
     argument_parser::parse_arguments,
-    autoreplies::{Autoreply, AutoreplyResponse, AutoreplySet, AutoreplySetMap},
+    autoreplies::{Autoreply, AutoreplyResponse, AutoreplySet, AutoreplySetMap, WeightedResponse},
+    autoreply_pattern::{AutoreplyPattern, PatternError},
+    autoreply_template::{expand_template, TemplateContext},
+    chat_config::ChatConfigModel,
     command_handler::{fail, succeed, succeed_with_message, HandlerError, HandlerResult},
     db::DatabaseRef,
 };
@@ -13,6 +19,42 @@ pub async fn handle_add_message(
     db: DatabaseRef,
     autoreply_set_map: AutoreplySetMap,
     args: &str,
+) -> HandlerResult {
+    add_text_message(
+        chat_id,
+        db,
+        autoreply_set_map,
+        args,
+        "/addmessage <nimi> <regex> <viesti>",
+        AutoreplyResponse::Literal,
+    )
+    .await
+}
+
+pub async fn handle_add_template(
+    chat_id: ChatId,
+    db: DatabaseRef,
+    autoreply_set_map: AutoreplySetMap,
+    args: &str,
+) -> HandlerResult {
+    add_text_message(
+        chat_id,
+        db,
+        autoreply_set_map,
+        args,
+        "/addtemplate <nimi> <regex> <pohja>",
+        AutoreplyResponse::Template,
+    )
+    .await
+}
+
+async fn add_text_message(
+    chat_id: ChatId,
+    db: DatabaseRef,
+    autoreply_set_map: AutoreplySetMap,
+    args: &str,
+    usage: &str,
+    text_response: fn(String) -> AutoreplyResponse,
 ) -> HandlerResult {
     let args = parse_arguments(args);
 
@@ -23,21 +65,19 @@ pub async fn handle_add_message(
         Ok((_, args)) => {
             if args.len() == 2 {
                 return succeed_with_message(
-                    "👀 Lähetä haluttu viesti tai tarra vastauksena alkuperäiseen komentoon.",
+                    "👀 Lähetä haluttu viesti, tarra, kuva, GIF, ääni- tai videoviesti vastauksena alkuperäiseen komentoon.",
                 );
             }
 
             if args.len() != 3 {
-                return fail(
-                    "Parametrien määrä väärin. Käytä muotoa: /addmessage <nimi> <regex> <viesti>",
-                );
+                return fail(format!("Parametrien määrä väärin. Käytä muotoa: {}", usage));
             }
 
             let name = &args[0];
-            let pattern_regex = parse_regex(&args)?;
+            let pattern = parse_pattern(&args)?;
 
-            let pattern_regex = match pattern_regex {
-                Some(regex) => regex,
+            let pattern = match pattern {
+                Some(pattern) => pattern,
                 None => {
                     return succeed();
                 }
@@ -48,10 +88,10 @@ pub async fn handle_add_message(
             add_message(
                 chat_id,
                 name,
-                pattern_regex,
+                pattern,
                 db,
                 autoreply_set_map,
-                AutoreplyResponse::Literal(response.to_string()),
+                text_response(response.to_string()),
             )
             .await?;
         }
@@ -65,6 +105,7 @@ pub async fn handle_add_message_reply(
     autoreply_set_map: AutoreplySetMap,
     message: &Message,
     previous_args: &str,
+    text_response: fn(String) -> AutoreplyResponse,
 ) -> HandlerResult {
     let previous_args = parse_arguments(previous_args);
     let chat_id = message.chat.id;
@@ -80,72 +121,671 @@ pub async fn handle_add_message_reply(
             }
 
             let name = &args[0];
-            let pattern_regex = parse_regex(&args)?;
+            let pattern = parse_pattern(&args)?;
 
-            let pattern_regex = match pattern_regex {
-                Some(regex) => regex,
+            let pattern = match pattern {
+                Some(pattern) => pattern,
                 None => {
                     return succeed();
                 }
             };
 
-            let response = if let Some(sticker) = message.sticker() {
-                AutoreplyResponse::Sticker(sticker.file_id.clone())
-            } else {
-                AutoreplyResponse::Literal(
-                    message
-                        .text()
-                        .map(String::from)
-                        .unwrap_or_else(|| String::from("[object Object]")),
-                )
+            let response = match get_message_response(message, text_response) {
+                Some(response) => response,
+                None => {
+                    return fail("Tämän tyyppisiä viestejä ei voi käyttää vastauksena. 😔");
+                }
             };
 
-            add_message(
-                chat_id,
-                name,
-                pattern_regex,
-                db,
-                autoreply_set_map,
-                response,
-            )
-            .await
+            add_message(chat_id, name, pattern, db, autoreply_set_map, response).await
         }
     }
 }
 
+fn get_message_response(
+    message: &Message,
+    text_response: fn(String) -> AutoreplyResponse,
+) -> Option<AutoreplyResponse> {
+    if let Some(sticker) = message.sticker() {
+        Some(AutoreplyResponse::Sticker(sticker.file_id.clone()))
+    } else if let Some(photo) = message.photo() {
+        // Telegram sends every available size of the photo, the last one being the largest.
+        photo
+            .last()
+            .map(|photo| AutoreplyResponse::Photo(photo.file_id.clone()))
+    } else if let Some(animation) = message.animation() {
+        Some(AutoreplyResponse::Animation(animation.file_id.clone()))
+    } else if let Some(voice) = message.voice() {
+        Some(AutoreplyResponse::Voice(voice.file_id.clone()))
+    } else if let Some(video_note) = message.video_note() {
+        Some(AutoreplyResponse::VideoNote(video_note.file_id.clone()))
+    } else if let Some(document) = message.document() {
+        Some(AutoreplyResponse::Document(document.file_id.clone()))
+    } else {
+        message.text().map(|text| text_response(text.to_string()))
+    }
+}
+
 async fn add_message(
     chat_id: ChatId,
     name: &str,
-    pattern_regex: Regex,
+    pattern: AutoreplyPattern,
     db: DatabaseRef,
     autoreply_set_map: std::sync::Arc<
         tokio::sync::RwLock<std::collections::HashMap<ChatId, AutoreplySet>>,
     >,
     response: AutoreplyResponse,
 ) -> HandlerResult {
-    let autoreply = Autoreply {
-        chat_id,
-        name: name.to_string(),
-        pattern_regex,
-        response,
-    };
-    db.add_autoreply(&autoreply).await?;
     let mut autoreply_set_map = autoreply_set_map.write().await;
-    autoreply_set_map
+    let autoreply_set = autoreply_set_map
         .entry(chat_id)
-        .or_insert_with(AutoreplySet::empty)
-        .add_autoreply(autoreply);
+        .or_insert_with(AutoreplySet::empty);
+
+    let previous = autoreply_set.get_autoreply(name).cloned();
+
+    // Redefining an autoreply replaces the pattern and the primary response, but keeps the rest
+    // of the response pool and the options.
+    let autoreply = match previous.clone() {
+        Some(mut autoreply) if !autoreply.responses.is_empty() => {
+            autoreply.pattern = pattern;
+            autoreply.responses[0].response = response;
+            autoreply
+        }
+        _ => Autoreply::new(chat_id, name.to_string(), pattern, response),
+    };
+
+    // Add to the regex set first, as combining the patterns can still fail.
+    if let Err(err) = autoreply_set.add_autoreply(autoreply.clone()) {
+        return fail(format!(
+            "Kuvion lisääminen muiden automaattisten vastausten joukkoon epäonnistui: {}",
+            err
+        ));
+    }
+
+    if let Err(err) = db.add_autoreply(&autoreply).await {
+        // Keep the set in line with the database
+        let rollback = match previous {
+            Some(previous) => autoreply_set.add_autoreply(previous),
+            None => autoreply_set.remove_autoreply(name).map(|_| ()),
+        };
+
+        if let Err(rollback_err) = rollback {
+            log::error!(
+                "Failed to roll back autoreply {} in chat {:?}: {}",
+                name,
+                chat_id,
+                rollback_err
+            );
+        }
+
+        return Err(err.into());
+    }
+
+    if previous.is_some() {
+        return succeed_with_message(format!("🎉 Päivitetty automaattinen vastaus {}", name));
+    }
 
     succeed_with_message(format!("🎉 Lisätty automaattinen vastaus {}", name))
 }
 
-fn parse_regex<'a>(args: &[std::borrow::Cow<'a, str>]) -> Result<Option<Regex>, HandlerError> {
-    let pattern_regex = Regex::new(&*args[1]);
-    let pattern_regex = match pattern_regex {
-        Ok(regex) => regex,
+fn parse_pattern<'a>(
+    args: &[std::borrow::Cow<'a, str>],
+) -> Result<Option<AutoreplyPattern>, HandlerError> {
+    let pattern = AutoreplyPattern::parse(&*args[1]);
+    let pattern = match pattern {
+        Ok(pattern) => pattern,
+        Err(err) => {
+            return fail(PatternError::from(err).to_string());
+        }
+    };
+
+    if let Err(err) = pattern.validate() {
+        return fail(err.to_string());
+    }
+
+    Ok(Some(pattern))
+}
+
+/// Parses exactly `N` arguments. Like in /addmessage, names with spaces are given in quotes.
+fn parse_exact_arguments<const N: usize>(
+    args: &str,
+    usage: &str,
+) -> Result<[String; N], HandlerError> {
+    let wrong_count = || fail(format!("Parametrien määrä väärin. Käytä muotoa: {}", usage));
+
+    if args.trim().is_empty() {
+        return wrong_count();
+    }
+
+    let args = match parse_arguments(args) {
+        Err(err) => {
+            return fail(format!("Parametrien parsinta epäonnistui: {}", err));
+        }
+        Ok((_, args)) => args,
+    };
+
+    args.into_iter()
+        .map(Cow::into_owned)
+        .collect::<Vec<_>>()
+        .try_into()
+        .or_else(|_| wrong_count())
+}
+
+pub async fn handle_list_messages(
+    chat_id: ChatId,
+    autoreply_set_map: AutoreplySetMap,
+) -> HandlerResult {
+    let autoreply_set_map = autoreply_set_map.read().await;
+
+    let names = autoreply_set_map
+        .get(&chat_id)
+        .map(|autoreply_set| {
+            autoreply_set
+                .iter()
+                .map(|autoreply| autoreply.name.as_str())
+                .sorted()
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    if names.is_empty() {
+        return succeed_with_message("Ei automaattisia vastauksia tällä kanavalla.");
+    }
+
+    succeed_with_message(format!(
+        "Automaattiset vastaukset ({}):\n{}",
+        names.len(),
+        names.join("\n")
+    ))
+}
+
+pub async fn handle_show_message(
+    chat_id: ChatId,
+    autoreply_set_map: AutoreplySetMap,
+    args: &str,
+) -> HandlerResult {
+    let [name] = parse_exact_arguments(args, "/showmessage <nimi>")?;
+    let name = name.as_str();
+    let autoreply_set_map = autoreply_set_map.read().await;
+
+    let autoreply = autoreply_set_map
+        .get(&chat_id)
+        .and_then(|autoreply_set| autoreply_set.get_autoreply(name));
+
+    match autoreply {
+        None => fail(format!("Automaattista vastausta {} ei löytynyt.", name)),
+        Some(autoreply) => {
+            let chance = autoreply
+                .chance
+                .map(|chance| chance.to_string())
+                .unwrap_or_else(|| String::from("kanavan oletus"));
+            let cooldown = autoreply
+                .cooldown
+                .map(|cooldown| format!("{} min", cooldown.as_secs() / 60))
+                .unwrap_or_else(|| String::from("ei"));
+            let enabled = if autoreply.enabled { "kyllä" } else { "ei" };
+
+            let responses = autoreply
+                .responses
+                .iter()
+                .enumerate()
+                .map(|(index, WeightedResponse { response, weight })| {
+                    format!("{}. {} (paino {})", index + 1, response, weight)
+                })
+                .join("\n");
+
+            succeed_with_message(format!(
+                "{}\nKuvio: {}\nRegex: {}\nTodennäköisyys: {}\nJäähy: {}\nKäytössä: {}\nVastaukset:\n{}",
+                autoreply.name,
+                autoreply.pattern,
+                autoreply.pattern.regex.as_str(),
+                chance,
+                cooldown,
+                enabled,
+                responses
+            ))
+        }
+    }
+}
+
+pub async fn handle_delete_message(
+    chat_id: ChatId,
+    db: DatabaseRef,
+    autoreply_set_map: AutoreplySetMap,
+    args: &str,
+) -> HandlerResult {
+    let [name] = parse_exact_arguments(args, "/deletemessage <nimi>")?;
+    let name = name.as_str();
+
+    if !db.remove_autoreply(chat_id, name).await? {
+        return fail(format!("Automaattista vastausta {} ei löytynyt.", name));
+    }
+
+    let mut autoreply_set_map = autoreply_set_map.write().await;
+
+    if let Some(autoreply_set) = autoreply_set_map.get_mut(&chat_id) {
+        autoreply_set
+            .remove_autoreply(name)
+            .context("Failed to remove autoreply from the regex set")?;
+    }
+
+    succeed_with_message(format!("🗑️ Poistettu automaattinen vastaus {}", name))
+}
+
+pub async fn handle_rename_message(
+    chat_id: ChatId,
+    db: DatabaseRef,
+    autoreply_set_map: AutoreplySetMap,
+    args: &str,
+) -> HandlerResult {
+    let [old_name, new_name] =
+        parse_exact_arguments(args, "/renamemessage <vanha nimi> <uusi nimi>")?;
+    let (old_name, new_name) = (old_name.as_str(), new_name.as_str());
+
+    let mut autoreply_set_map = autoreply_set_map.write().await;
+
+    let autoreply_set = match autoreply_set_map.get_mut(&chat_id) {
+        Some(autoreply_set) if autoreply_set.get_autoreply(old_name).is_some() => autoreply_set,
+        _ => {
+            return fail(format!("Automaattista vastausta {} ei löytynyt.", old_name));
+        }
+    };
+
+    if autoreply_set.get_autoreply(new_name).is_some() {
+        return fail(format!(
+            "Automaattinen vastaus {} on jo olemassa.",
+            new_name
+        ));
+    }
+
+    if !db.rename_autoreply(chat_id, old_name, new_name).await? {
+        return fail(format!("Automaattista vastausta {} ei löytynyt.", old_name));
+    }
+
+    if let Some(mut autoreply) = autoreply_set
+        .remove_autoreply(old_name)
+        .context("Failed to remove renamed autoreply")?
+    {
+        autoreply.name = new_name.to_string();
+        autoreply_set
+            .add_autoreply(autoreply)
+            .context("Failed to re-add renamed autoreply")?;
+    }
+
+    succeed_with_message(format!(
+        "✏️ Automaattinen vastaus {} nimetty uudelleen: {}",
+        old_name, new_name
+    ))
+}
+
+pub async fn handle_set_message_chance(
+    chat_id: ChatId,
+    db: DatabaseRef,
+    autoreply_set_map: AutoreplySetMap,
+    args: &str,
+) -> HandlerResult {
+    let [name, chance] = parse_exact_arguments(args, "/setmessagechance <nimi> <0-1|oletus>")?;
+    let name = name.as_str();
+
+    let chance = match chance.as_str() {
+        "default" | "oletus" => None,
+        chance => match chance.parse::<f64>() {
+            Ok(chance) if (0.0..=1.0).contains(&chance) => Some(chance),
+            _ => {
+                return fail("Todennäköisyyden pitää olla luku väliltä 0-1 tai \"oletus\".");
+            }
+        },
+    };
+
+    update_autoreply(chat_id, db, autoreply_set_map, name, |autoreply| {
+        autoreply.chance = chance;
+        Ok(())
+    })
+    .await?;
+
+    match chance {
+        Some(chance) => succeed_with_message(format!(
+            "🎲 Automaattisen vastauksen {} todennäköisyys asetettu arvoon {}",
+            name, chance
+        )),
+        None => succeed_with_message(format!(
+            "🎲 Automaattinen vastaus {} käyttää kanavan oletustodennäköisyyttä",
+            name
+        )),
+    }
+}
+
+pub async fn handle_set_message_cooldown(
+    chat_id: ChatId,
+    db: DatabaseRef,
+    autoreply_set_map: AutoreplySetMap,
+    args: &str,
+) -> HandlerResult {
+    let [name, minutes] = parse_exact_arguments(args, "/setmessagecooldown <nimi> <minuutit>")?;
+    let name = name.as_str();
+
+    let minutes = match minutes.parse::<u32>() {
+        Ok(minutes) => minutes,
+        Err(_) => {
+            return fail("Jäähyn pitää olla minuutteja kokonaislukuna.");
+        }
+    };
+
+    let cooldown = match minutes {
+        0 => None,
+        minutes => Some(Duration::from_secs(u64::from(minutes) * 60)),
+    };
+
+    update_autoreply(chat_id, db, autoreply_set_map, name, |autoreply| {
+        autoreply.cooldown = cooldown;
+        Ok(())
+    })
+    .await?;
+
+    match cooldown {
+        Some(_) => succeed_with_message(format!(
+            "⏳ Automaattinen vastaus {} laukeaa korkeintaan kerran {} minuutissa",
+            name, minutes
+        )),
+        None => succeed_with_message(format!(
+            "⏳ Automaattisen vastauksen {} jäähy poistettu",
+            name
+        )),
+    }
+}
+
+pub async fn handle_set_message_enabled(
+    chat_id: ChatId,
+    db: DatabaseRef,
+    autoreply_set_map: AutoreplySetMap,
+    args: &str,
+    enabled: bool,
+) -> HandlerResult {
+    let usage = if enabled {
+        "/enablemessage <nimi>"
+    } else {
+        "/disablemessage <nimi>"
+    };
+    let [name] = parse_exact_arguments(args, usage)?;
+    let name = name.as_str();
+
+    update_autoreply(chat_id, db, autoreply_set_map, name, |autoreply| {
+        autoreply.enabled = enabled;
+        Ok(())
+    })
+    .await?;
+
+    if enabled {
+        succeed_with_message(format!("✅ Automaattinen vastaus {} otettu käyttöön", name))
+    } else {
+        succeed_with_message(format!(
+            "💤 Automaattinen vastaus {} poistettu käytöstä",
+            name
+        ))
+    }
+}
+
+async fn update_autoreply(
+    chat_id: ChatId,
+    db: DatabaseRef,
+    autoreply_set_map: AutoreplySetMap,
+    name: &str,
+    update: impl FnOnce(&mut Autoreply) -> HandlerResult<()>,
+) -> HandlerResult<()> {
+    let mut autoreply_set_map = autoreply_set_map.write().await;
+
+    let autoreply_set = autoreply_set_map.get_mut(&chat_id);
+
+    let mut autoreply = match autoreply_set
+        .as_ref()
+        .and_then(|autoreply_set| autoreply_set.get_autoreply(name))
+    {
+        Some(autoreply) => autoreply.clone(),
+        None => {
+            return fail(format!("Automaattista vastausta {} ei löytynyt.", name));
+        }
+    };
+
+    update(&mut autoreply)?;
+
+    if !db.update_autoreply(&autoreply).await? {
+        return fail(format!("Automaattista vastausta {} ei löytynyt.", name));
+    }
+
+    if let Some(autoreply_set) = autoreply_set {
+        autoreply_set
+            .add_autoreply(autoreply)
+            .context("Failed to replace updated autoreply")?;
+    }
+
+    Ok(())
+}
+
+pub async fn handle_add_response(
+    chat_id: ChatId,
+    db: DatabaseRef,
+    autoreply_set_map: AutoreplySetMap,
+    args: &str,
+) -> HandlerResult {
+    let args = match parse_arguments(args) {
+        Err(err) => {
+            return fail(format!("Parametrien parsinta epäonnistui: {}", err));
+        }
+        Ok((_, args)) => args,
+    };
+
+    if args.len() == 2 {
+        parse_weight(&args[1])?;
+
+        return succeed_with_message(
+            "👀 Lähetä haluttu vastaus viestinä tai mediana vastauksena alkuperäiseen komentoon.",
+        );
+    }
+
+    if args.len() != 3 {
+        return fail(
+            "Parametrien määrä väärin. Käytä muotoa: /addresponse <nimi> <paino> <viesti>",
+        );
+    }
+
+    let name = &args[0];
+    let weight = parse_weight(&args[1])?;
+    let response = AutoreplyResponse::Literal(args[2].to_string());
+
+    add_response(chat_id, db, autoreply_set_map, name, weight, response).await
+}
+
+pub async fn handle_add_response_reply(
+    db: DatabaseRef,
+    autoreply_set_map: AutoreplySetMap,
+    message: &Message,
+    previous_args: &str,
+) -> HandlerResult {
+    let args = match parse_arguments(previous_args) {
         Err(err) => {
-            return fail(format!("Regex-lausekkeen parsinta epäonnistui: {}", err));
+            return fail(format!(
+                "Komennon parametrien parsinta epäonnistui: {}",
+                err
+            ));
+        }
+        Ok((_, args)) => args,
+    };
+
+    if args.len() != 2 {
+        return fail("Parametrien määrä väärin.");
+    }
+
+    let name = &args[0];
+    let weight = parse_weight(&args[1])?;
+
+    let response = match get_message_response(message, AutoreplyResponse::Literal) {
+        Some(response) => response,
+        None => {
+            return fail("Tämän tyyppisiä viestejä ei voi käyttää vastauksena. 😔");
+        }
+    };
+
+    add_response(
+        message.chat.id,
+        db,
+        autoreply_set_map,
+        name,
+        weight,
+        response,
+    )
+    .await
+}
+
+async fn add_response(
+    chat_id: ChatId,
+    db: DatabaseRef,
+    autoreply_set_map: AutoreplySetMap,
+    name: &str,
+    weight: u32,
+    response: AutoreplyResponse,
+) -> HandlerResult {
+    let mut response_count = 0;
+
+    update_autoreply(chat_id, db, autoreply_set_map, name, |autoreply| {
+        autoreply
+            .responses
+            .push(WeightedResponse { response, weight });
+        response_count = autoreply.responses.len();
+        Ok(())
+    })
+    .await?;
+
+    succeed_with_message(format!(
+        "🎉 Lisätty vastaus automaattiseen vastaukseen {} ({} vastausta)",
+        name, response_count
+    ))
+}
+
+pub async fn handle_remove_response(
+    chat_id: ChatId,
+    db: DatabaseRef,
+    autoreply_set_map: AutoreplySetMap,
+    args: &str,
+) -> HandlerResult {
+    let [name, number] = parse_exact_arguments(args, "/removeresponse <nimi> <numero>")?;
+    let name = name.as_str();
+
+    let number = match number.parse::<usize>() {
+        Ok(number) => number,
+        Err(_) => {
+            return fail("Vastauksen numeron pitää olla kokonaisluku.");
         }
     };
-    Ok(Some(pattern_regex))
+
+    update_autoreply(chat_id, db, autoreply_set_map, name, |autoreply| {
+        if number == 0 || number > autoreply.responses.len() {
+            return fail(format!(
+                "Vastausta numero {} ei löytynyt. Numerot näet komennolla /showmessage \"{}\"",
+                number, name
+            ));
+        }
+
+        if autoreply.responses.len() == 1 {
+            return fail(
+                "Automaattisella vastauksella pitää olla ainakin yksi vastaus. Käytä /deletemessage -komentoa.",
+            );
+        }
+
+        autoreply.responses.remove(number - 1);
+        Ok(())
+    })
+    .await?;
+
+    succeed_with_message(format!(
+        "🗑️ Poistettu vastaus {} automaattisesta vastauksesta {}",
+        number, name
+    ))
+}
+
+fn parse_weight(weight: &str) -> Result<u32, HandlerError> {
+    match weight.parse::<u32>() {
+        Ok(weight) if weight > 0 => Ok(weight),
+        _ => fail("Painon pitää olla positiivinen kokonaisluku."),
+    }
+}
+
+pub async fn handle_test_message(
+    message: &Message,
+    autoreply_set_map: AutoreplySetMap,
+    chat_config_map: Arc<ChatConfigModel>,
+    text: &str,
+) -> HandlerResult {
+    // When replying to a message, test the replied message instead of the command argument
+    let (tested_message, text) = match message.reply_to_message() {
+        Some(original_message) if text.trim().is_empty() => (
+            original_message,
+            original_message.text().unwrap_or_default(),
+        ),
+        _ => (message, text.trim()),
+    };
+
+    if text.is_empty() {
+        return fail("Käytä muotoa /testmessage <viesti> tai vastaa testattavaan viestiin.");
+    }
+
+    let sender_name = tested_message
+        .from()
+        .map(|user| user.first_name.as_str())
+        .unwrap_or_default();
+
+    let chat_config = chat_config_map.get(message.chat.id).await?;
+    let autoreply_set_map = autoreply_set_map.read().await;
+
+    let matches = autoreply_set_map
+        .get(&message.chat.id)
+        .map(|autoreply_set| autoreply_set.find_matches(text))
+        .unwrap_or_default();
+
+    if matches.is_empty() {
+        return succeed_with_message("🔍 Viesti ei laukaise yhtään automaattista vastausta.");
+    }
+
+    let descriptions = matches
+        .iter()
+        .sorted_by_key(|autoreply| &autoreply.name)
+        .map(|autoreply| {
+            let disabled = if autoreply.enabled {
+                ""
+            } else {
+                " (pois käytöstä)"
+            };
+
+            let responses = autoreply
+                .responses
+                .iter()
+                .map(|WeightedResponse { response, weight }| {
+                    let response = match response {
+                        AutoreplyResponse::Template(template) => {
+                            let context = TemplateContext {
+                                captures: autoreply.pattern.regex.captures(text),
+                                sender_name,
+                                date: chat_config.today(),
+                                date_format: chat_config.locale.date_format(),
+                            };
+                            expand_template(template, &context, &mut rand::thread_rng())
+                        }
+                        response => response.to_string(),
+                    };
+
+                    format!("  → {} (paino {})", response, weight)
+                })
+                .join("\n");
+
+            format!(
+                "{}{}\nKuvio: {}\n{}",
+                autoreply.name, disabled, autoreply.pattern, responses
+            )
+        })
+        .join("\n\n");
+
+    succeed_with_message(format!(
+        "🔍 Viesti laukaisee {} automaattista vastausta:\n\n{}",
+        matches.len(),
+        descriptions
+    ))
 }
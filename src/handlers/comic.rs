@@ -0,0 +1,388 @@
+use std::{io::Cursor, str::FromStr};
+
+use anyhow::Context;
+use chrono::{Duration, NaiveDate, Utc};
+use image::{ImageFormat, ImageOutputFormat};
+use itertools::Itertools;
+use teloxide::{prelude::*, types::InputFile};
+
+use crate::{
+    chat_config::ChatConfigModel,
+    comics::{
+        Comic, ComicError, ComicRegistry, ComicRequest, ComicSource, ComicUpload,
+        DEFAULT_SUBSCRIPTION_COMICS,
+    },
+    command_handler::{fail, succeed, succeed_with_message, HandlerResult},
+    db::DatabaseRef,
+    telegram_utils::re_encode_image,
+};
+
+/// Random picks are fetched at most this many times to avoid strips the chat has seen recently.
+const MAX_RANDOM_ATTEMPTS: u32 = 5;
+
+/// How long a strip shown in a chat counts as a recent repeat.
+const RECENT_HISTORY_DAYS: i64 = 90;
+
+/// Finds the requested strip from the cache or fetches it from the source.
+async fn resolve_comic(
+    db: &DatabaseRef,
+    chat_id: ChatId,
+    source: &dyn ComicSource,
+    request: ComicRequest,
+    today: NaiveDate,
+) -> anyhow::Result<Comic> {
+    let date = match request {
+        ComicRequest::Latest => source.latest_date(),
+        request => request.date(today),
+    };
+
+    if let Some(date) = date {
+        if let Some(comic) = db.get_cached_comic(source.id(), date).await? {
+            return Ok(comic);
+        }
+    }
+
+    let mut comic = source.fetch(request, today).await?;
+
+    if request.is_random() {
+        let since = Utc::now().naive_utc() - Duration::days(RECENT_HISTORY_DAYS);
+
+        for _ in 1..MAX_RANDOM_ATTEMPTS {
+            let is_repeat = db
+                .was_comic_shown_since(chat_id, source.id(), comic.image_url.as_str(), since)
+                .await?;
+
+            if !is_repeat {
+                break;
+            }
+
+            comic = source.fetch(request, today).await?;
+        }
+    }
+
+    // The strip may have been uploaded before even if it had to be scraped again
+    if let Some(date) = comic.date {
+        if let Some(cached) = db.get_cached_comic(source.id(), date).await? {
+            if cached.image_url == comic.image_url {
+                comic.file_id = cached.file_id;
+            }
+        }
+    }
+
+    Ok(comic)
+}
+
+/// Telegram rejects uploaded photos larger than this.
+const MAX_PHOTO_BYTES: usize = 10 * 1024 * 1024;
+
+/// Telegram requires the width and height of a photo to add up to at most this.
+const MAX_PHOTO_DIMENSION_SUM: u32 = 10_000;
+
+/// Returns the image as is if Telegram accepts it as a photo, otherwise resized and re-encoded
+/// as JPEG. Also returns the file extension of the result.
+fn prepare_photo(image_bytes: Vec<u8>) -> anyhow::Result<(Vec<u8>, &'static str)> {
+    let reader = image::io::Reader::new(Cursor::new(&image_bytes)).with_guessed_format()?;
+    let format = reader.format();
+    let (width, height) = reader
+        .into_dimensions()
+        .context("Failed to read comic image dimensions")?;
+
+    let fits = width + height <= MAX_PHOTO_DIMENSION_SUM && image_bytes.len() <= MAX_PHOTO_BYTES;
+
+    match format {
+        Some(format @ (ImageFormat::Jpeg | ImageFormat::Png)) if fits => {
+            Ok((image_bytes, format.extensions_str()[0]))
+        }
+        _ => {
+            let image_jpg = re_encode_image(
+                &image_bytes,
+                ImageOutputFormat::Jpeg(90),
+                Some(MAX_PHOTO_DIMENSION_SUM),
+            )
+            .context("Failed to re-encode comic image")?;
+
+            Ok((image_jpg, "jpg"))
+        }
+    }
+}
+
+async fn upload_comic(
+    bot: &AutoSend<Bot>,
+    comic_registry: &ComicRegistry,
+    chat_id: ChatId,
+    comic: &Comic,
+    upload: ComicUpload,
+) -> anyhow::Result<Message> {
+    let photo = match upload {
+        ComicUpload::Url => InputFile::url(comic.image_url.clone()),
+        ComicUpload::Download => {
+            let image_bytes = comic_registry
+                .download_image(&comic.image_url)
+                .await
+                .context("Failed to download comic image")?;
+            let (image_bytes, extension) = prepare_photo(image_bytes)?;
+
+            InputFile::memory(image_bytes).file_name(format!("comic.{}", extension))
+        }
+    };
+
+    Ok(bot.send_photo(chat_id, photo).await?)
+}
+
+/// Sends the comic using the earlier upload if there is one, and then the configured upload
+/// followed by the other one.
+async fn send_comic_photo(
+    bot: &AutoSend<Bot>,
+    comic_registry: &ComicRegistry,
+    chat_id: ChatId,
+    comic: &Comic,
+) -> anyhow::Result<Message> {
+    if let Some(file_id) = &comic.file_id {
+        match bot.send_photo(chat_id, InputFile::file_id(file_id)).await {
+            Ok(message) => return Ok(message),
+            Err(err) => log::warn!("Failed to send cached comic {}: {}", file_id, err),
+        }
+    }
+
+    let [upload, fallback] = comic_registry.upload().with_fallback();
+
+    match upload_comic(bot, comic_registry, chat_id, comic, upload).await {
+        Ok(message) => Ok(message),
+        Err(err) => {
+            log::warn!(
+                "Failed to send comic {} by {}, trying {}: {:#}",
+                comic.image_url,
+                upload.as_str(),
+                fallback.as_str(),
+                err
+            );
+
+            upload_comic(bot, comic_registry, chat_id, comic, fallback).await
+        }
+    }
+}
+
+pub async fn send_comic(
+    bot: &AutoSend<Bot>,
+    db: &DatabaseRef,
+    chat_id: ChatId,
+    comic_registry: &ComicRegistry,
+    source: &dyn ComicSource,
+    request: ComicRequest,
+    today: NaiveDate,
+) -> anyhow::Result<()> {
+    let comic = resolve_comic(db, chat_id, source, request, today).await?;
+
+    let message = send_comic_photo(bot, comic_registry, chat_id, &comic)
+        .await
+        .with_context(|| format!("Failed to send {}", source.name()))?;
+
+    // The comic has been sent already, so failing to remember it should not make the caller retry
+    if let Some(date) = comic.date {
+        let file_id = message
+            .photo()
+            .and_then(|sizes| sizes.last())
+            .map(|size| size.file_id.as_str());
+
+        if let Err(err) = db
+            .cache_comic(source.id(), date, comic.image_url.as_str(), file_id)
+            .await
+        {
+            log::error!("Failed to cache {}: {:#}", source.name(), err);
+        }
+    }
+
+    let now = Utc::now().naive_utc();
+
+    if let Err(err) = db
+        .add_comic_history(
+            chat_id,
+            source.id(),
+            comic.image_url.as_str(),
+            now,
+            now - Duration::days(RECENT_HISTORY_DAYS),
+        )
+        .await
+    {
+        log::error!("Failed to add {} to history: {:#}", source.name(), err);
+    }
+
+    Ok(())
+}
+
+/// Returns the comics posted by the chat's comics subscriptions, in order.
+pub async fn get_subscription_comics(
+    db: &DatabaseRef,
+    chat_id: ChatId,
+) -> anyhow::Result<Vec<String>> {
+    let comic_ids = db.get_chat_comics(chat_id).await?;
+
+    if comic_ids.is_empty() {
+        return Ok(DEFAULT_SUBSCRIPTION_COMICS
+            .iter()
+            .map(|id| id.to_string())
+            .collect());
+    }
+
+    Ok(comic_ids)
+}
+
+pub async fn handle_comic_request(
+    bot: &AutoSend<Bot>,
+    chat_id: ChatId,
+    db: &DatabaseRef,
+    chat_config_map: &ChatConfigModel,
+    comic_registry: &ComicRegistry,
+    id: &str,
+    request: ComicRequest,
+) -> HandlerResult {
+    let source = match comic_registry.get(id) {
+        Some(source) => source,
+        None => {
+            return fail(format!(
+                "Tuntematon sarjakuva {}. Sarjakuvat näet komennolla /comics",
+                id
+            ));
+        }
+    };
+
+    if request.needs_dates() && !source.supports_dates() {
+        return fail(format!(
+            "Sarjakuvaa {} ei voi hakea päivämäärän perusteella.",
+            source.name()
+        ));
+    }
+
+    let today = chat_config_map.get(chat_id).await?.today();
+
+    if let Err(err) = send_comic(bot, db, chat_id, comic_registry, source, request, today).await {
+        // Requests outside the archive are the user's mistake rather than an error
+        if let Some(comic_error) = err.chain().find_map(|err| err.downcast_ref::<ComicError>()) {
+            return fail(comic_error.to_string());
+        }
+
+        return Err(err.into());
+    }
+
+    succeed()
+}
+
+const COMIC_USAGE: &str = "Käytä muotoa /comic <sarjakuva> [latest|random|history|VVVV-KK-PP|<N>v], missä <N>v hakee tämän päivän sarjakuvan N vuoden takaa. Sarjakuvat näet komennolla /comics";
+
+/// Handles a comic request given as arguments, e.g. `random` or `1988-06-19`.
+pub async fn handle_comic_args(
+    bot: &AutoSend<Bot>,
+    chat_id: ChatId,
+    db: &DatabaseRef,
+    chat_config_map: &ChatConfigModel,
+    comic_registry: &ComicRegistry,
+    id: &str,
+    args: &str,
+) -> HandlerResult {
+    let request = match ComicRequest::from_str(&args.trim().to_lowercase()) {
+        Ok(request) => request,
+        Err(_) => return fail(COMIC_USAGE),
+    };
+
+    handle_comic_request(
+        bot,
+        chat_id,
+        db,
+        chat_config_map,
+        comic_registry,
+        id,
+        request,
+    )
+    .await
+}
+
+pub async fn handle_comic(
+    bot: &AutoSend<Bot>,
+    chat_id: ChatId,
+    db: &DatabaseRef,
+    chat_config_map: &ChatConfigModel,
+    comic_registry: &ComicRegistry,
+    args: &str,
+) -> HandlerResult {
+    let (id, args) = match args.trim().split_once(char::is_whitespace) {
+        Some((id, args)) => (id, args),
+        None if !args.trim().is_empty() => (args.trim(), ""),
+        None => return fail(COMIC_USAGE),
+    };
+
+    handle_comic_args(bot, chat_id, db, chat_config_map, comic_registry, id, args).await
+}
+
+pub async fn handle_list_comics(
+    chat_id: ChatId,
+    db: DatabaseRef,
+    comic_registry: &ComicRegistry,
+) -> HandlerResult {
+    let comics = comic_registry
+        .iter()
+        .map(|source| {
+            let dates = if source.supports_dates() {
+                ", myös päivämäärällä"
+            } else {
+                ""
+            };
+            format!("{} ({}{})", source.id(), source.name(), dates)
+        })
+        .join("\n");
+
+    let subscription_comics = get_subscription_comics(&db, chat_id).await?.join(", ");
+
+    succeed_with_message(format!(
+        "📰 Sarjakuvat:\n{}\n\nTilausten sarjakuvat: {}\n\nHae sarjakuva komennolla /comic <sarjakuva> [latest|random|history|VVVV-KK-PP|<N>v]",
+        comics, subscription_comics
+    ))
+}
+
+pub async fn handle_set_subscription_comics(
+    chat_id: ChatId,
+    db: DatabaseRef,
+    comic_registry: &ComicRegistry,
+    args: &str,
+) -> HandlerResult {
+    let ids = args
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|id| !id.is_empty())
+        .collect::<Vec<_>>();
+
+    if ids.is_empty() {
+        return fail("Anna sarjakuvat järjestyksessä, esim. /setsubscriptioncomics fokit fingerpori, tai default palauttaaksesi oletukset. Sarjakuvat näet komennolla /comics");
+    }
+
+    if let [id] = ids[..] {
+        if id.eq_ignore_ascii_case("default") || id.eq_ignore_ascii_case("oletus") {
+            db.set_chat_comics(chat_id, &[]).await?;
+
+            return succeed_with_message(format!(
+                "Tilausten sarjakuvat palautettu oletuksiin: {}",
+                DEFAULT_SUBSCRIPTION_COMICS.join(", ")
+            ));
+        }
+    }
+
+    let mut comic_ids = Vec::new();
+
+    for id in ids {
+        let source = match comic_registry.get(id) {
+            Some(source) => source,
+            None => {
+                return fail(format!(
+                    "Tuntematon sarjakuva {}. Sarjakuvat näet komennolla /comics",
+                    id
+                ));
+            }
+        };
+
+        if !comic_ids.contains(&source.id()) {
+            comic_ids.push(source.id());
+        }
+    }
+
+    db.set_chat_comics(chat_id, &comic_ids).await?;
+
+    succeed_with_message(format!("Tilausten sarjakuvat: {}", comic_ids.join(", ")))
+}
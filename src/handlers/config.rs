@@ -1,10 +1,12 @@
-use std::sync::Arc;
+use std::{str::FromStr, sync::Arc};
 
+use itertools::Itertools;
 use teloxide::types::ChatId;
 
 use crate::{
-    chat_config::ChatConfigModel,
-    command_handler::{succeed_with_message, HandlerResult},
+    chat_config::{ChatConfigModel, ChatSetting},
+    command_handler::{fail, succeed_with_message, HandlerResult},
+    scheduler::SchedulerWakeup,
 };
 
 pub async fn handle_set_autoreply_chance(
@@ -12,10 +14,92 @@ pub async fn handle_set_autoreply_chance(
     chat_config_map: Arc<ChatConfigModel>,
     value: f64,
 ) -> HandlerResult {
-    chat_config_map.set_autoreply_chance(chat_id, value).await?;
+    set_setting(
+        chat_id,
+        chat_config_map,
+        ChatSetting::AutoreplyChance,
+        &value.to_string(),
+    )
+    .await?;
 
     succeed_with_message(format!(
         "🎉 Automaattisen vastauksen todennäköisyys asetettu arvoon {}",
         value
     ))
 }
+
+pub async fn handle_show_config(
+    chat_id: ChatId,
+    chat_config_map: Arc<ChatConfigModel>,
+) -> HandlerResult {
+    let config = chat_config_map.get(chat_id).await?;
+
+    let settings = ChatSetting::ALL
+        .iter()
+        .map(|setting| {
+            format!(
+                "{} = {}\n  {}",
+                setting.key(),
+                setting.get(&config),
+                setting.description()
+            )
+        })
+        .join("\n");
+
+    succeed_with_message(format!(
+        "⚙️ Kanavan asetukset:\n{}\n\nMuuta asetusta komennolla /set <asetus> <arvo>",
+        settings
+    ))
+}
+
+pub async fn handle_set(
+    chat_id: ChatId,
+    chat_config_map: Arc<ChatConfigModel>,
+    scheduler_wakeup: &SchedulerWakeup,
+    args: &str,
+) -> HandlerResult {
+    // The value is the rest of the line, as it can contain spaces
+    let (key, value) = match args.trim().split_once(char::is_whitespace) {
+        Some((key, value)) => (key, value.trim()),
+        None => {
+            return fail("Käytä muotoa /set <asetus> <arvo>. Asetukset näet komennolla /config");
+        }
+    };
+
+    let setting = match ChatSetting::from_str(key) {
+        Ok(setting) => setting,
+        Err(err) => {
+            return fail(err.to_string());
+        }
+    };
+
+    set_setting(chat_id, chat_config_map, setting, value).await?;
+
+    // Subscription times are in the chat's timezone
+    if setting == ChatSetting::Timezone {
+        scheduler_wakeup.wake();
+    }
+
+    succeed_with_message(format!(
+        "🎉 Asetus {} asetettu arvoon {}",
+        setting.key(),
+        value
+    ))
+}
+
+async fn set_setting(
+    chat_id: ChatId,
+    chat_config_map: Arc<ChatConfigModel>,
+    setting: ChatSetting,
+    value: &str,
+) -> HandlerResult<()> {
+    let mut config = chat_config_map.get(chat_id).await?;
+
+    if let Err(err) = setting.set(&mut config, value) {
+        return fail(err.to_string());
+    }
+
+    chat_config_map.save(config).await?;
+
+    Ok(())
+}
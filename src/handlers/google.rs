@@ -1,12 +1,14 @@
+use std::sync::Arc;
+
 use anyhow::Context;
-use chrono::Local;
 use teloxide::{prelude::*, types::ParseMode};
 
 use crate::{
+    chat_config::{ChatConfig, ChatConfigModel},
     command_handler::{fail, succeed, succeed_with_message, HandlerError, HandlerResult},
     db::DatabaseRef,
     google::{
-        get_events_to_announce, EventExt, GoogleCalendarClientFactory,
+        get_events_to_announce, EventExt, EventsSummary, GoogleCalendarClientFactory,
         GoogleCalendarClientFactoryState, UpcomingEvent,
     },
     telegram_utils::telegram_escape,
@@ -155,6 +157,7 @@ pub async fn print_calendar_events(
     bot: &AutoSend<Bot>,
     message: Message,
     db: DatabaseRef,
+    chat_config_map: Arc<ChatConfigModel>,
     google_calendar_client_factory: GoogleCalendarClientFactory,
 ) -> HandlerResult {
     let chat_id = message.chat.id;
@@ -162,6 +165,8 @@ pub async fn print_calendar_events(
     let google_calendar_client_factory =
         get_google_calendar_client_factory(&google_calendar_client_factory)?;
 
+    let chat_config = chat_config_map.get(chat_id).await?;
+
     let calendar_id = db.get_connected_calendar_id(chat_id).await?;
 
     let calendar_id = match calendar_id {
@@ -178,13 +183,66 @@ pub async fn print_calendar_events(
     let client =
         get_google_calendar_client_for_user(google_calendar_client_factory, sender.id).await?;
 
-    let now = Local::now();
-    let events_summary = get_events_to_announce(&client, &calendar_id, now).await?;
+    let events_summary = get_events_to_announce(&client, &calendar_id, chat_config.now()).await?;
 
     if events_summary.today.is_empty() && events_summary.upcoming.is_empty() {
         return succeed_with_message("Ei tulevia tapahtumia kalenterissa. 😔");
     }
 
+    send_events_summary(bot, chat_id, events_summary, &chat_config).await?;
+
+    succeed()
+}
+
+/// Posts the daily digest of an `events` subscription, using the calendar connected to the chat
+/// and the Google login of the user who connected it. Nothing is posted if there are no events.
+pub async fn handle_calendar_digest(
+    bot: &AutoSend<Bot>,
+    chat_id: ChatId,
+    db: &DatabaseRef,
+    chat_config_map: &ChatConfigModel,
+    google_calendar_client_factory: &GoogleCalendarClientFactory,
+) -> HandlerResult {
+    let google_calendar_client_factory =
+        get_google_calendar_client_factory(google_calendar_client_factory)?;
+
+    let chat_config = chat_config_map.get(chat_id).await?;
+
+    let (user_id, calendar_id) = match db.get_connected_calendar(chat_id).await? {
+        None => {
+            return fail("📅 Tapahtumatilausta ei voitu hoitaa, koska kanavaan ei ole kytketty kalenteria. Käytä /connectgooglecalendar -komentoa.");
+        }
+        Some(connected_calendar) => connected_calendar,
+    };
+
+    let client = match google_calendar_client_factory
+        .create_client_for_user(user_id)
+        .await?
+    {
+        None => {
+            return fail("📅 Tapahtumatilausta ei voitu hoitaa, koska kalenterin kytkenyt käyttäjä ei ole enää Google-tunnistautunut.");
+        }
+        Some(client) => client,
+    };
+
+    let events_summary = get_events_to_announce(&client, &calendar_id, chat_config.now()).await?;
+
+    if events_summary.today.is_empty() && events_summary.upcoming.is_empty() {
+        log::info!("No events to announce in chat {:?}", chat_id);
+        return succeed();
+    }
+
+    send_events_summary(bot, chat_id, events_summary, &chat_config).await?;
+
+    succeed()
+}
+
+async fn send_events_summary(
+    bot: &AutoSend<Bot>,
+    chat_id: ChatId,
+    events_summary: EventsSummary,
+    chat_config: &ChatConfig,
+) -> anyhow::Result<()> {
     let mut message = String::new();
 
     if !events_summary.today.is_empty() {
@@ -192,10 +250,10 @@ pub async fn print_calendar_events(
         for event in events_summary.today {
             let event_start = event.start.unwrap();
             let event_timestamp = if let Some(date_time) = event_start.date_time {
-                date_time
-                    .with_timezone(&Local)
-                    .format(" (%H:%M)")
-                    .to_string()
+                let time = date_time
+                    .with_timezone(&chat_config.timezone)
+                    .format(chat_config.locale.time_format());
+                format!(" ({})", time)
             } else {
                 "".to_string()
             };
@@ -213,8 +271,8 @@ pub async fn print_calendar_events(
     if !events_summary.upcoming.is_empty() {
         message.push_str("*Tulevat tapahtumat*:\n");
         for UpcomingEvent { event, days } in events_summary.upcoming {
-            let event_local_time = event.get_start_date().unwrap();
-            let event_date = event_local_time.format("%d.%m.%Y");
+            let event_local_time = event.get_start_date(&chat_config.timezone).unwrap();
+            let event_date = chat_config.format_date(event_local_time);
             let days_label = match days {
                 1 => String::from("Huomenna"),
                 days => format!("{} päivän päästä", days),
@@ -230,5 +288,5 @@ pub async fn print_calendar_events(
         .parse_mode(ParseMode::MarkdownV2)
         .await?;
 
-    succeed()
+    Ok(())
 }
@@ -1,36 +1,49 @@
-mod hs;
-
 mod dude_carpet;
 pub use dude_carpet::handle_dude_carpet;
 
 mod get_excuse;
 pub use get_excuse::handle_get_excuse;
 
-mod fingerpori;
-pub use fingerpori::handle_fingerpori;
-pub use fingerpori::handle_randompori;
-
-mod fokit;
-pub use fokit::handle_fokit;
-pub use fokit::handle_random_fokit;
-
-mod lasaga;
-pub use lasaga::handle_lasaga;
-pub use lasaga::handle_random_lasaga;
+mod comic;
+pub use comic::get_subscription_comics;
+pub use comic::handle_comic;
+pub use comic::handle_comic_args;
+pub use comic::handle_comic_request;
+pub use comic::handle_list_comics;
+pub use comic::handle_set_subscription_comics;
+pub use comic::send_comic;
 
 mod subscription;
+pub use subscription::handle_list_subscriptions;
+pub use subscription::handle_set_subscription_catch_up;
 pub use subscription::handle_subscribe;
+pub use subscription::handle_unsubscribe;
 
 mod autoreply;
 pub use autoreply::handle_add_message;
 pub use autoreply::handle_add_message_reply;
+pub use autoreply::handle_add_response;
+pub use autoreply::handle_add_response_reply;
+pub use autoreply::handle_add_template;
+pub use autoreply::handle_delete_message;
+pub use autoreply::handle_list_messages;
+pub use autoreply::handle_remove_response;
+pub use autoreply::handle_rename_message;
+pub use autoreply::handle_set_message_chance;
+pub use autoreply::handle_set_message_cooldown;
+pub use autoreply::handle_set_message_enabled;
+pub use autoreply::handle_show_message;
+pub use autoreply::handle_test_message;
 
 mod config;
+pub use config::handle_set;
 pub use config::handle_set_autoreply_chance;
+pub use config::handle_show_config;
 
 mod google;
 pub use google::connect_google_calendar;
 pub use google::disconnect_google_calendar;
+pub use google::handle_calendar_digest;
 pub use google::handle_finish_google_auth;
 pub use google::handle_start_google_auth;
 pub use google::print_calendar_events;
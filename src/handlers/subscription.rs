@@ -1,21 +1,33 @@
-use std::str::FromStr;
+use std::{str::FromStr, sync::Arc};
 
-use chrono::NaiveTime;
+use chrono::NaiveDateTime;
+use itertools::Itertools;
 use teloxide::types::ChatId;
 
 use crate::{
+    chat_config::{ChatConfig, ChatConfigModel},
     command_handler::{fail, succeed_with_message, HandlerResult},
     db::DatabaseRef,
-    subscriptions::{Subscription, SubscriptionType, TIME_FORMAT},
+    schedule::Schedule,
+    scheduler::SchedulerWakeup,
+    subscriptions::{SubscriptionType, DEFAULT_CATCH_UP_MINUTES},
 };
 
 pub async fn handle_subscribe(
     chat_id: ChatId,
     db: DatabaseRef,
-    subscription_type: &str,
-    time: &str,
+    chat_config_map: Arc<ChatConfigModel>,
+    scheduler_wakeup: &SchedulerWakeup,
+    args: &str,
 ) -> HandlerResult {
-    let kind = SubscriptionType::from_str(subscription_type);
+    let (kind, schedule) = match args.trim().split_once(char::is_whitespace) {
+        Some(args) => args,
+        None => {
+            return fail("Käytä muotoa /subscribe <tyyppi> <aikataulu>, esim. /subscribe comics every monday 08:00");
+        }
+    };
+
+    let kind = SubscriptionType::from_str(kind);
 
     let kind = match kind {
         Ok(kind) => kind,
@@ -24,28 +36,122 @@ pub async fn handle_subscribe(
         }
     };
 
-    let time = NaiveTime::parse_from_str(time, TIME_FORMAT);
-
-    let time = match time {
-        Ok(time) => time,
-        Err(_) => {
-            return fail("Epäkelpo ajankohta. Käytä muotoa HH:MM");
+    let schedule = match Schedule::from_str(schedule) {
+        Ok(schedule) => schedule,
+        Err(err) => {
+            return fail(err.to_string());
         }
     };
 
-    let subscription = Subscription {
-        chat_id,
-        kind,
-        time,
-    };
+    let chat_config = chat_config_map.get(chat_id).await?;
+    let now = chat_config.now().naive_local();
+
+    let id = db.add_subscription(chat_id, kind, &schedule, now).await?;
+    scheduler_wakeup.wake();
 
-    db.add_subscription(&subscription).await?;
+    log::info!(
+        "Added subscription {} {} \"{}\" for chat {:?}",
+        id,
+        kind.as_str(),
+        schedule,
+        chat_id
+    );
 
-    log::info!("Added subscription: {:?}", subscription);
+    let next_run = schedule.next_after(now);
 
     succeed_with_message(format!(
-        "🎉 Lisätty tilaus {}, päivittäin kello {}",
+        "🎉 Lisätty tilaus #{} {}: {}{}",
+        id,
         kind.as_str(),
-        time.format(TIME_FORMAT)
+        schedule,
+        format_next_run(next_run, &chat_config)
+    ))
+}
+
+pub async fn handle_list_subscriptions(
+    chat_id: ChatId,
+    db: DatabaseRef,
+    chat_config_map: Arc<ChatConfigModel>,
+) -> HandlerResult {
+    let subscriptions = db.get_chat_subscriptions(chat_id).await?;
+
+    if subscriptions.is_empty() {
+        return succeed_with_message(
+            "Kanavalla ei ole tilauksia. Lisää tilaus komennolla /subscribe",
+        );
+    }
+
+    let chat_config = chat_config_map.get(chat_id).await?;
+    let now = chat_config.now().naive_local();
+
+    let subscriptions = subscriptions
+        .iter()
+        .map(|subscription| {
+            let catch_up = if subscription.catch_up_minutes == DEFAULT_CATCH_UP_MINUTES {
+                String::new()
+            } else {
+                format!(", myöhässä enintään {} min", subscription.catch_up_minutes)
+            };
+
+            format!(
+                "#{} {}: {}{}{}",
+                subscription.id,
+                subscription.kind.as_str(),
+                subscription.schedule,
+                catch_up,
+                format_next_run(subscription.next_run(now), &chat_config)
+            )
+        })
+        .join("\n");
+
+    succeed_with_message(format!(
+        "📅 Kanavan tilaukset:\n{}\n\nPoista tilaus komennolla /unsubscribe <numero>",
+        subscriptions
+    ))
+}
+
+fn format_next_run(next_run: Option<NaiveDateTime>, chat_config: &ChatConfig) -> String {
+    match next_run {
+        Some(next_run) => format!(" (seuraavaksi {})", chat_config.format_date_time(next_run)),
+        None => String::new(),
+    }
+}
+
+pub async fn handle_unsubscribe(
+    chat_id: ChatId,
+    db: DatabaseRef,
+    scheduler_wakeup: &SchedulerWakeup,
+    id: i64,
+) -> HandlerResult {
+    if !db.remove_subscription(chat_id, id).await? {
+        return fail(format!(
+            "Tilausta #{} ei löytynyt. Tilaukset näet komennolla /subscriptions",
+            id
+        ));
+    }
+
+    scheduler_wakeup.wake();
+
+    log::info!("Removed subscription {} from chat {:?}", id, chat_id);
+
+    succeed_with_message(format!("🗑️ Tilaus #{} poistettu.", id))
+}
+
+pub async fn handle_set_subscription_catch_up(
+    chat_id: ChatId,
+    db: DatabaseRef,
+    id: i64,
+    minutes: u32,
+) -> HandlerResult {
+    if !db.set_subscription_catch_up(chat_id, id, minutes).await? {
+        return fail(format!(
+            "Tilausta #{} ei löytynyt. Tilaukset näet komennolla /subscriptions",
+            id
+        ));
+    }
+
+    succeed_with_message(format!(
+        "🎉 Tilaus #{} toimitetaan vielä, kun se on enintään {} minuuttia myöhässä.",
+        id, minutes
     ))
 }
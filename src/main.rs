@@ -3,7 +3,7 @@ use std::{collections::HashMap, sync::Arc};
 use anyhow::Context;
 use autoreplies::AutoreplySet;
 use chrono::{DateTime, Utc};
-use command_handler::handle_command;
+use command_handler::{handle_command, CommandServices, PermissionChecker};
 use google::GoogleCalendarClientFactory;
 use message_handler::handle_message;
 use teloxide::{
@@ -17,19 +17,27 @@ use tokio::sync::RwLock;
 use crate::{
     autoreplies::{create_autoreply_set_map, StickerCache},
     chat_config::ChatConfigModel,
+    comics::{ComicRegistry, ComicUpload},
     db::open_and_prepare_db,
     google::GoogleCalendarClientFactoryState,
-    scheduler::scheduled_event_handler,
+    rate_limiter::ReplyRateLimiter,
+    scheduler::{scheduled_event_handler, SchedulerWakeup},
 };
 
 mod argument_parser;
 mod autoreplies;
+mod autoreply_pattern;
+mod autoreply_template;
 mod chat_config;
+mod comics;
 mod command_handler;
 mod db;
 mod google;
 mod handlers;
 mod message_handler;
+mod quiet_hours;
+mod rate_limiter;
+mod schedule;
 mod scheduler;
 mod subscriptions;
 mod telegram_utils;
@@ -43,33 +51,108 @@ pub enum Command {
     #[command(description = "Apuva")]
     Help,
 
-    #[command(description = "Hae uusin Fingerpori")]
-    Fingerpori,
+    #[command(description = "Hae Fingerpori: [random|history|VVVV-KK-PP|<N>v]")]
+    Fingerpori(String),
 
     #[command(description = "Hae satunnainen Fingerpori")]
     Randompori,
 
-    #[command(description = "Hae uusin Fok_It")]
-    Fokit,
+    #[command(description = "Hae Fok_It: [random|history|VVVV-KK-PP|<N>v]")]
+    Fokit(String),
 
     #[command(description = "Hae satunnainen Fok_It")]
     RandomFokit,
 
-    #[command(description = "im sorry jon")]
-    Lasaga,
+    #[command(description = "im sorry jon: [random|history|VVVV-KK-PP|<N>v]")]
+    Lasaga(String),
 
     #[command(description = "im sorry jon xD")]
     RandomLasaga,
 
-    #[command(description = "Tilaa ajoitettu tapahtuma", parse_with = "split")]
-    Subscribe { kind: String, time: String },
+    #[command(description = "Hae sarjakuva: <sarjakuva> [latest|random|VVVV-KK-PP]")]
+    Comic(String),
 
-    #[command(description = "Lisää automaattinen vastaus")]
+    #[command(description = "Listaa saatavilla olevat sarjakuvat")]
+    Comics,
+
+    #[command(
+        description = "Valitse comics-tilausten sarjakuvat järjestyksessä, esim. fokit fingerpori, tai default"
+    )]
+    SetSubscriptionComics(String),
+
+    #[command(
+        description = "Tilaa ajoitettu tapahtuma: <comics|events> <aikataulu>, esim. 08:00, 08:00 ma-pe, every monday 08:00, 1st of month 12:00 tai 0 8 * * 1-5. Viikonpäivät annetaan osana aikataulua"
+    )]
+    Subscribe(String),
+
+    #[command(description = "Listaa kanavan tilaukset")]
+    Subscriptions,
+
+    #[command(description = "Poista tilaus numeron perusteella")]
+    Unsubscribe(i64),
+
+    #[command(
+        description = "Aseta kuinka monta minuuttia myöhässä tilaus vielä toimitetaan",
+        parse_with = "split"
+    )]
+    SetSubscriptionCatchUp { id: i64, minutes: u32 },
+
+    #[command(
+        description = "Lisää automaattinen vastaus. Kuvion edessä voi olla tila: regex:, word:, text: tai fuzzy:"
+    )]
     AddMessage(String),
 
+    #[command(
+        description = "Lisää automaattinen vastaus pohjasta ($1, ${nimi}, {sender}, {date}, {a|b|c})"
+    )]
+    AddTemplate(String),
+
+    #[command(description = "Lisää painotettu vaihtoehtoinen vastaus automaattiseen vastaukseen")]
+    AddResponse(String),
+
+    #[command(description = "Poista vaihtoehtoinen vastaus automaattisesta vastauksesta")]
+    RemoveResponse(String),
+
+    #[command(
+        description = "Testaa mitkä automaattiset vastaukset viesti laukaisisi (tai vastaa viestiin)"
+    )]
+    TestMessage(String),
+
+    #[command(description = "Listaa automaattiset vastaukset")]
+    ListMessages,
+
+    #[command(description = "Näytä automaattinen vastaus")]
+    ShowMessage(String),
+
+    #[command(description = "Poista automaattinen vastaus")]
+    DeleteMessage(String),
+
+    #[command(
+        description = "Nimeä automaattinen vastaus uudelleen. Useamman sanan nimet lainausmerkeissä"
+    )]
+    RenameMessage(String),
+
+    #[command(description = "Aseta automaattisen vastauksen oma todennäköisyys (0-1 tai oletus)")]
+    SetMessageChance(String),
+
+    #[command(description = "Aseta automaattisen vastauksen jäähy minuutteina (0 poistaa)")]
+    SetMessageCooldown(String),
+
+    #[command(description = "Ota automaattinen vastaus käyttöön")]
+    EnableMessage(String),
+
+    #[command(description = "Poista automaattinen vastaus käytöstä")]
+    DisableMessage(String),
+
     #[command(description = "Aseta automaattisen vastauksen todennäköisyys")]
     SetAutoreplyChance(f64),
 
+    #[command(description = "Näytä kanavan asetukset")]
+    Config,
+
+    #[command(description = "Muuta kanavan asetusta: <asetus> <arvo>")]
+    Set(String),
+
     #[command(
         description = "Anna pääsy kaikkiin henkilötietoihisi (oikeesti vaan google kalentereihin bro)"
     )]
@@ -148,21 +231,59 @@ async fn main() -> anyhow::Result<()> {
 
     let sticker_cache = Arc::new(StickerCache::new(db.clone(), chat_config_map.clone()));
 
+    let reply_rate_limiter = Arc::new(ReplyRateLimiter::new());
+
+    let bot_owner_id = std::env::var("BOT_OWNER_ID")
+        .ok()
+        .map(|id| id.parse::<u64>().map(UserId))
+        .transpose()
+        .context("BOT_OWNER_ID must be a Telegram user id")?;
+
+    if bot_owner_id.is_none() {
+        log::info!("BOT_OWNER_ID not configured, owner-only commands are disabled.");
+    }
+
+    let permission_checker = Arc::new(PermissionChecker::new(bot_owner_id));
+
+    let (scheduler_wakeup, receive_scheduler_wakeup) = SchedulerWakeup::new();
+
+    let comic_upload = std::env::var("COMIC_UPLOAD")
+        .ok()
+        .map(|upload| upload.parse::<ComicUpload>())
+        .transpose()
+        .context("COMIC_UPLOAD must be url or download")?
+        .unwrap_or(ComicUpload::Url);
+
+    let comic_registry = Arc::new(ComicRegistry::new(comic_upload));
+
     let mut dispatcher = Dispatcher::builder(bot.clone(), handler(start_time))
         .default_handler(ignore_update)
         .dependencies(dptree::deps![
             db.clone(),
             autoreply_set_map,
-            chat_config_map,
+            chat_config_map.clone(),
             sticker_cache,
-            gcal_client_factory
+            reply_rate_limiter,
+            gcal_client_factory.clone(),
+            permission_checker,
+            CommandServices {
+                scheduler_wakeup,
+                comic_registry: comic_registry.clone(),
+            }
         ])
         .enable_ctrlc_handler()
         .build();
 
     let (_, event_handler_result) = futures::join!(
         dispatcher.dispatch(),
-        scheduled_event_handler(bot, db.clone())
+        scheduled_event_handler(
+            bot,
+            db.clone(),
+            chat_config_map,
+            gcal_client_factory,
+            comic_registry,
+            receive_scheduler_wakeup
+        )
     );
 
     event_handler_result?;
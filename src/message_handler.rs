@@ -1,4 +1,4 @@
-use std::{io::Cursor, sync::Arc};
+use std::sync::Arc;
 
 use anyhow::Context;
 use image::ImageOutputFormat;
@@ -9,11 +9,17 @@ use teloxide::{
 
 use crate::{
     autoreplies::{AutoreplyResponse, AutoreplySetMap, StickerCache},
+    autoreply_template::{expand_template, TemplateContext},
     chat_config::ChatConfigModel,
+    command_handler::{send_handler_result, Permission, PermissionChecker},
     db::DatabaseRef,
-    handlers, Command,
+    handlers,
+    rate_limiter::ReplyRateLimiter,
+    telegram_utils::re_encode_image,
+    Command,
 };
 
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_message(
     bot: AutoSend<Bot>,
     db: DatabaseRef,
@@ -21,6 +27,8 @@ pub async fn handle_message(
     autoreply_set_map: AutoreplySetMap,
     chat_config_map: Arc<ChatConfigModel>,
     sticker_cache: Arc<StickerCache>,
+    reply_rate_limiter: Arc<ReplyRateLimiter>,
+    permission_checker: Arc<PermissionChecker>,
 ) -> anyhow::Result<()> {
     let chat_id = message.chat.id;
     let mut is_reply_to_me = false;
@@ -47,19 +55,67 @@ pub async fn handle_message(
                 bot.send_message(chat_id, "😳").await?;
                 return Ok(());
             }
-            Ok(Command::AddMessage(args)) => {
-                handlers::handle_add_message_reply(db, autoreply_set_map, &message, &args).await?;
+            Ok(
+                command @ (Command::AddMessage(_)
+                | Command::AddResponse(_)
+                | Command::AddTemplate(_)),
+            ) if !permission_checker
+                .is_allowed(&bot, &message, &command)
+                .await? =>
+            {
+                bot.send_message(chat_id, Permission::required_for(&command).denied_message())
+                    .await?;
                 return Ok(());
             }
+            Ok(Command::AddMessage(args)) => {
+                let result = handlers::handle_add_message_reply(
+                    db,
+                    autoreply_set_map,
+                    &message,
+                    &args,
+                    AutoreplyResponse::Literal,
+                )
+                .await;
+                return send_handler_result(&bot, chat_id, result).await;
+            }
+            Ok(Command::AddResponse(args)) => {
+                let result =
+                    handlers::handle_add_response_reply(db, autoreply_set_map, &message, &args)
+                        .await;
+                return send_handler_result(&bot, chat_id, result).await;
+            }
+            Ok(Command::AddTemplate(args)) => {
+                let result = handlers::handle_add_message_reply(
+                    db,
+                    autoreply_set_map,
+                    &message,
+                    &args,
+                    AutoreplyResponse::Template,
+                )
+                .await;
+                return send_handler_result(&bot, chat_id, result).await;
+            }
             _ => {}
         }
     }
 
     let chat_config = chat_config_map.get(chat_id).await?;
 
+    // Quiet hours only suppress replies nobody asked for
+    let is_quiet_hours = chat_config.quiet_hours.contains(chat_config.now().time());
+
     if let Some(sticker) = message.sticker() {
         if sticker.set_name.is_none() {
             // If this is a sticker set without a set name, it is acccshually a WebP image sent as a sticker
+            if is_quiet_hours {
+                return Ok(());
+            }
+
+            if !reply_rate_limiter.try_acquire(&chat_config) {
+                log::info!("Reply rate limit reached in chat {:?}", chat_id);
+                return Ok(());
+            }
+
             let sticker_file = bot
                 .get_file(&sticker.file_id)
                 .await
@@ -68,7 +124,7 @@ pub async fn handle_message(
             bot.download_file(&sticker_file.file_path, &mut sticker_buffer)
                 .await?;
 
-            let image_png = re_encode_image(sticker_buffer, ImageOutputFormat::Jpeg(95))?;
+            let image_png = re_encode_image(&sticker_buffer, ImageOutputFormat::Jpeg(95), None)?;
 
             let mut payload = SendPhoto::new(chat_id, InputFile::memory(image_png));
             payload.reply_to_message_id = Some(message.id);
@@ -90,7 +146,10 @@ pub async fn handle_message(
                     Some(reply_sticker) => {
                         let p: f64 = rand::random();
 
-                        if p < chat_config.autoreply_chance {
+                        if p < chat_config.autoreply_chance
+                            && !is_quiet_hours
+                            && reply_rate_limiter.try_acquire(&chat_config)
+                        {
                             bot.send_sticker(chat_id, InputFile::file_id(reply_sticker.file_id))
                                 .await
                                 .context("Failed to send response sticker")?;
@@ -103,6 +162,10 @@ pub async fn handle_message(
         return Ok(());
     }
 
+    if is_quiet_hours && !is_reply_to_me {
+        return Ok(());
+    }
+
     let text = message.text().unwrap_or_default();
 
     let autoreply_set_map = autoreply_set_map.read().await;
@@ -116,29 +179,85 @@ pub async fn handle_message(
     };
 
     let mut reply_message = String::new();
-    let autoreply_chance = if is_reply_to_me {
-        1.0
-    } else {
-        chat_config.autoreply_chance
-    };
+    let mut replied_with = Vec::new();
 
-    for reply in autoreply_set.get_matches(text) {
-        let p: f64 = rand::random();
+    let sender_name = message
+        .from()
+        .map(|user| user.first_name.as_str())
+        .unwrap_or_default();
 
-        if p > autoreply_chance {
-            continue;
-        }
+    let matches = autoreply_set.get_matches(text, chat_config.autoreply_chance, is_reply_to_me);
+
+    if matches.is_empty() {
+        return Ok(());
+    }
+
+    if !reply_rate_limiter.try_acquire(&chat_config) {
+        log::info!("Reply rate limit reached in chat {:?}", chat_id);
+        return Ok(());
+    }
 
-        match &reply.response {
+    for reply in matches {
+        let response = match reply.choose_response(&mut rand::thread_rng()) {
+            Some(response) => response,
+            None => continue,
+        };
+
+        match response {
             AutoreplyResponse::Literal(text) => {
                 if !reply_message.is_empty() {
                     reply_message.push(' ');
                 }
                 reply_message.push_str(text);
+                replied_with.push(reply);
+            }
+            AutoreplyResponse::Template(template) => {
+                let context = TemplateContext {
+                    captures: reply.pattern.regex.captures(text),
+                    sender_name,
+                    date: chat_config.today(),
+                    date_format: chat_config.locale.date_format(),
+                };
+                let text = expand_template(template, &context, &mut rand::thread_rng());
+
+                if !reply_message.is_empty() {
+                    reply_message.push(' ');
+                }
+                reply_message.push_str(&text);
+                replied_with.push(reply);
             }
             AutoreplyResponse::Sticker(sticker_id) => {
                 bot.send_sticker(chat_id, InputFile::file_id(sticker_id))
                     .await?;
+                autoreply_set.mark_triggered(reply);
+                return Ok(());
+            }
+            AutoreplyResponse::Photo(file_id) => {
+                bot.send_photo(chat_id, InputFile::file_id(file_id)).await?;
+                autoreply_set.mark_triggered(reply);
+                return Ok(());
+            }
+            AutoreplyResponse::Animation(file_id) => {
+                bot.send_animation(chat_id, InputFile::file_id(file_id))
+                    .await?;
+                autoreply_set.mark_triggered(reply);
+                return Ok(());
+            }
+            AutoreplyResponse::Voice(file_id) => {
+                bot.send_voice(chat_id, InputFile::file_id(file_id)).await?;
+                autoreply_set.mark_triggered(reply);
+                return Ok(());
+            }
+            AutoreplyResponse::VideoNote(file_id) => {
+                bot.send_video_note(chat_id, InputFile::file_id(file_id))
+                    .await?;
+                autoreply_set.mark_triggered(reply);
+                return Ok(());
+            }
+            AutoreplyResponse::Document(file_id) => {
+                bot.send_document(chat_id, InputFile::file_id(file_id))
+                    .await?;
+                autoreply_set.mark_triggered(reply);
                 return Ok(());
             }
         }
@@ -146,16 +265,11 @@ pub async fn handle_message(
 
     if !reply_message.is_empty() {
         bot.send_message(chat_id, reply_message).await?;
+
+        for reply in replied_with {
+            autoreply_set.mark_triggered(reply);
+        }
     }
 
     Ok(())
 }
-
-fn re_encode_image(sticker_buffer: Vec<u8>, format: ImageOutputFormat) -> anyhow::Result<Vec<u8>> {
-    let image =
-        image::load_from_memory(&sticker_buffer).context("Failed to load sticker image :(")?;
-    let mut image_jpg = Vec::new();
-    let mut image_jpg_cursor = Cursor::new(&mut image_jpg);
-    image.write_to(&mut image_jpg_cursor, format)?;
-    Ok(image_jpg)
-}
@@ -0,0 +1,123 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::Context;
+use chrono::NaiveTime;
+use itertools::Itertools;
+
+use crate::subscriptions::TIME_FORMAT;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeWindow {
+    /// Windows where `end` is before `start` wrap around midnight, e.g. 23:00-07:00.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// Times of day during which the bot does not send unrequested replies.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QuietHours(pub Vec<TimeWindow>);
+
+impl QuietHours {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        self.0.iter().any(|window| window.contains(time))
+    }
+}
+
+/// Parses a comma separated list of windows, e.g. `23:00-07:00,13:00-14:00`.
+/// An empty string means no quiet hours.
+impl FromStr for QuietHours {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let windows = s
+            .split(',')
+            .map(str::trim)
+            .filter(|window| !window.is_empty())
+            .map(|window| {
+                let (start, end) = window
+                    .split_once('-')
+                    .with_context(|| format!("Invalid time window: {}", window))?;
+                let start = NaiveTime::parse_from_str(start.trim(), TIME_FORMAT)
+                    .with_context(|| format!("Invalid time: {}", start))?;
+                let end = NaiveTime::parse_from_str(end.trim(), TIME_FORMAT)
+                    .with_context(|| format!("Invalid time: {}", end))?;
+
+                Ok(TimeWindow { start, end })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(QuietHours(windows))
+    }
+}
+
+impl fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let windows = self
+            .0
+            .iter()
+            .map(|window| {
+                format!(
+                    "{}-{}",
+                    window.start.format(TIME_FORMAT),
+                    window.end.format(TIME_FORMAT)
+                )
+            })
+            .join(",");
+
+        write!(f, "{}", windows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms(hour, minute, 0)
+    }
+
+    #[test]
+    fn parse_and_format() {
+        let quiet_hours = QuietHours::from_str("23:00-07:00, 13:00-14:30").unwrap();
+        assert_eq!(quiet_hours.0.len(), 2);
+        assert_eq!(quiet_hours.to_string(), "23:00-07:00,13:00-14:30");
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert_eq!(QuietHours::from_str("").unwrap(), QuietHours::default());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(QuietHours::from_str("23:00").is_err());
+        assert!(QuietHours::from_str("25:00-07:00").is_err());
+    }
+
+    #[test]
+    fn contains_time_within_day() {
+        let quiet_hours = QuietHours::from_str("13:00-14:00").unwrap();
+        assert!(quiet_hours.contains(time(13, 30)));
+        assert!(!quiet_hours.contains(time(14, 0)));
+        assert!(!quiet_hours.contains(time(3, 0)));
+    }
+
+    #[test]
+    fn contains_time_over_midnight() {
+        let quiet_hours = QuietHours::from_str("23:00-07:00").unwrap();
+        assert!(quiet_hours.contains(time(23, 30)));
+        assert!(quiet_hours.contains(time(3, 0)));
+        assert!(!quiet_hours.contains(time(7, 0)));
+        assert!(!quiet_hours.contains(time(12, 0)));
+    }
+}
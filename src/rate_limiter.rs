@@ -0,0 +1,105 @@
+use std::time::Instant;
+
+use dashmap::DashMap;
+use teloxide::types::ChatId;
+
+use crate::chat_config::ChatConfig;
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn full(capacity: u32, now: Instant) -> Self {
+        Self {
+            tokens: capacity as f64,
+            last_refill: now,
+        }
+    }
+
+    fn try_take(&mut self, capacity: u32, tokens_per_hour: u32, now: Instant) -> bool {
+        let elapsed_hours = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64()
+            / 3600.0;
+
+        self.tokens = (self.tokens + elapsed_hours * tokens_per_hour as f64).min(capacity as f64);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Limits how often the bot replies to messages on its own (autoreplies, stickers) per chat.
+/// Explicit commands are not limited.
+pub struct ReplyRateLimiter {
+    buckets: DashMap<ChatId, TokenBucket>,
+}
+
+impl ReplyRateLimiter {
+    pub fn new() -> Self {
+        Self {
+            buckets: DashMap::new(),
+        }
+    }
+
+    /// Returns true if the bot is allowed to reply in the chat right now, consuming one reply.
+    pub fn try_acquire(&self, config: &ChatConfig) -> bool {
+        if config.reply_burst == 0 {
+            return true;
+        }
+
+        let now = Instant::now();
+
+        self.buckets
+            .entry(config.chat_id)
+            .or_insert_with(|| TokenBucket::full(config.reply_burst, now))
+            .try_take(config.reply_burst, config.replies_per_hour, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn allow_burst_then_limit() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::full(3, now);
+
+        assert!(bucket.try_take(3, 60, now));
+        assert!(bucket.try_take(3, 60, now));
+        assert!(bucket.try_take(3, 60, now));
+        assert!(!bucket.try_take(3, 60, now));
+    }
+
+    #[test]
+    fn refill_over_time() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::full(1, now);
+
+        assert!(bucket.try_take(1, 60, now));
+        assert!(!bucket.try_take(1, 60, now + Duration::from_secs(30)));
+        assert!(bucket.try_take(1, 60, now + Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn do_not_refill_past_capacity() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::full(2, now);
+        let later = now + Duration::from_secs(24 * 3600);
+
+        assert!(bucket.try_take(2, 60, later));
+        assert!(bucket.try_take(2, 60, later));
+        assert!(!bucket.try_take(2, 60, later));
+    }
+}
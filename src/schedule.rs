@@ -0,0 +1,555 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use itertools::Itertools;
+use once_cell::sync::OnceCell;
+use regex::Regex;
+use thiserror::Error;
+
+const WEEKDAY_NAMES: [&str; 7] = ["ma", "ti", "ke", "to", "pe", "la", "su"];
+
+/// Set of weekdays stored as a bit mask, Monday being the lowest bit.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Weekdays(u8);
+
+impl Weekdays {
+    pub const ALL: Weekdays = Weekdays(0b111_1111);
+    pub const WORKDAYS: Weekdays = Weekdays(0b001_1111);
+    pub const WEEKEND: Weekdays = Weekdays(0b110_0000);
+
+    /// Returns None if the mask is empty or has bits beyond Sunday.
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        if bits == 0 || bits & !Self::ALL.0 != 0 {
+            None
+        } else {
+            Some(Weekdays(bits))
+        }
+    }
+
+    pub fn contains(&self, weekday: Weekday) -> bool {
+        self.0 & (1 << weekday.num_days_from_monday()) != 0
+    }
+}
+
+fn parse_weekday(s: &str) -> anyhow::Result<u32> {
+    match WEEKDAY_NAMES.iter().position(|name| *name == s) {
+        Some(index) => Ok(index as u32),
+        None => Weekday::from_str(s)
+            .map(|weekday| weekday.num_days_from_monday())
+            .map_err(|_| anyhow::anyhow!("Invalid weekday: {}", s)),
+    }
+}
+
+impl FromStr for Weekdays {
+    type Err = anyhow::Error;
+
+    /// Parses either a named set (e.g. `arkisin`) or a comma-separated list of days and day ranges,
+    /// e.g. `ma,ke,pe` or `ma-pe`. Ranges can wrap around the end of the week, e.g. `pe-ma`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+
+        match s.as_str() {
+            "päivittäin" | "daily" => return Ok(Weekdays::ALL),
+            "arkisin" | "weekdays" => return Ok(Weekdays::WORKDAYS),
+            "viikonloppuisin" | "weekends" => return Ok(Weekdays::WEEKEND),
+            _ => {}
+        }
+
+        let mut bits = 0;
+
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            match part.split_once('-') {
+                Some((first, last)) => {
+                    let first = parse_weekday(first.trim())?;
+                    let last = parse_weekday(last.trim())?;
+                    let mut day = first;
+                    loop {
+                        bits |= 1 << day;
+                        if day == last {
+                            break;
+                        }
+                        day = (day + 1) % 7;
+                    }
+                }
+                None => bits |= 1 << parse_weekday(part)?,
+            }
+        }
+
+        Weekdays::from_bits(bits).ok_or_else(|| anyhow::anyhow!("No weekdays given"))
+    }
+}
+
+impl fmt::Display for Weekdays {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Weekdays::ALL => write!(f, "päivittäin"),
+            Weekdays::WORKDAYS => write!(f, "arkisin"),
+            Weekdays::WEEKEND => write!(f, "viikonloppuisin"),
+            weekdays => {
+                let names = WEEKDAY_NAMES
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, _)| weekdays.0 & (1 << index) != 0)
+                    .map(|(_, name)| name)
+                    .join(",");
+                write!(f, "{}", names)
+            }
+        }
+    }
+}
+
+/// Bits 1-31, one for each day of the month.
+const ALL_DAYS_OF_MONTH: u32 = !1;
+/// Bits 1-12, one for each month.
+const ALL_MONTHS: u16 = 0b1_1111_1111_1110;
+
+/// How far ahead to look for the next run. Long enough to always reach a February 29th.
+const MAX_LOOKAHEAD_DAYS: i64 = 8 * 366;
+
+#[derive(Debug, Error)]
+pub enum ScheduleError {
+    #[error("Aikataulua \"{0}\" ei tunnistettu. Käytä esim. 08:00, \"every monday 08:00\", \"1st of month 12:00\", \"every 2 hours between 9 and 17\" tai cron-muotoa \"0 8 * * 1-5\".")]
+    Unrecognized(String),
+    #[error("Epäkelpo cron-kenttä: {0}")]
+    InvalidCronField(String),
+    #[error("Epäkelpo kellonaika: {0}")]
+    InvalidTime(String),
+    #[error("Aikataulu ei laukeaisi koskaan.")]
+    NeverFires,
+}
+
+/// When a subscription runs. Both cron expressions and the natural forms compile down to the
+/// times of day plus the days they apply to.
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    /// The expression as given by the user.
+    expression: String,
+    /// Sorted times of day.
+    times: Vec<NaiveTime>,
+    days_of_month: u32,
+    months: u16,
+    weekdays: Weekdays,
+}
+
+impl Schedule {
+    fn new(times: Vec<NaiveTime>, days_of_month: u32, weekdays: Weekdays) -> Self {
+        Self {
+            expression: String::new(),
+            times: times.into_iter().sorted().dedup().collect(),
+            days_of_month,
+            months: ALL_MONTHS,
+            weekdays,
+        }
+    }
+
+    /// Returns the first time the schedule fires strictly after the given time.
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        (0..MAX_LOOKAHEAD_DAYS)
+            .map(|offset| after.date() + Duration::days(offset))
+            .filter(|date| self.matches_date(*date))
+            .find_map(|date| {
+                self.times
+                    .iter()
+                    .find(|time| date > after.date() || **time > after.time())
+                    .map(|time| date.and_time(*time))
+            })
+    }
+
+    /// Returns the last time the schedule fires at or before the given time.
+    pub fn last_at_or_before(&self, at: NaiveDateTime) -> Option<NaiveDateTime> {
+        (0..MAX_LOOKAHEAD_DAYS)
+            .map(|offset| at.date() - Duration::days(offset))
+            .filter(|date| self.matches_date(*date))
+            .find_map(|date| {
+                self.times
+                    .iter()
+                    .rev()
+                    .find(|time| date < at.date() || **time <= at.time())
+                    .map(|time| date.and_time(*time))
+            })
+    }
+
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+
+        let day_of_month = self.days_of_month & (1 << date.day()) != 0;
+        let weekday = self.weekdays.contains(date.weekday());
+
+        // Like in cron, a date matches either restriction if both days of month and weekdays are given
+        match (
+            self.days_of_month == ALL_DAYS_OF_MONTH,
+            self.weekdays == Weekdays::ALL,
+        ) {
+            (true, _) => weekday,
+            (false, true) => day_of_month,
+            (false, false) => day_of_month || weekday,
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = ScheduleError;
+
+    /// Parses a cron expression (`minute hour day-of-month month day-of-week`) or one of the
+    /// natural forms: one or more times of day (`08:00`, `08:00,12:30`) or an interval
+    /// (`every 2 hours between 9 and 17`, `every 30 minutes`), optionally preceded by the days
+    /// (`every monday`, `arkisin`, `ma-pe`, `1st of month`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expression = s.split_whitespace().join(" ");
+        let normalized = expression.to_lowercase();
+
+        let mut schedule = if is_cron(&normalized) {
+            parse_cron(&normalized)?
+        } else {
+            parse_natural(&normalized)?
+        };
+
+        if schedule.times.is_empty()
+            || schedule
+                .next_after(NaiveDate::from_ymd(2000, 1, 1).and_hms(0, 0, 0))
+                .is_none()
+        {
+            return Err(ScheduleError::NeverFires);
+        }
+
+        schedule.expression = expression;
+        Ok(schedule)
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+fn is_cron(s: &str) -> bool {
+    let fields = s.split(' ').collect::<Vec<_>>();
+
+    fields.len() == 5
+        && fields.iter().all(|field| {
+            field
+                .chars()
+                .all(|c| c.is_ascii_digit() || matches!(c, '*' | ',' | '-' | '/'))
+        })
+}
+
+fn parse_cron(s: &str) -> Result<Schedule, ScheduleError> {
+    let fields = s.split(' ').collect::<Vec<_>>();
+
+    let minutes = parse_cron_field(fields[0], 0, 59)?;
+    let hours = parse_cron_field(fields[1], 0, 23)?;
+    let days_of_month = parse_cron_field(fields[2], 1, 31)? as u32;
+    let months = parse_cron_field(fields[3], 1, 12)? as u16;
+    let days_of_week = parse_cron_field(fields[4], 0, 7)?;
+
+    // Both 0 and 7 are Sunday in cron, while Monday is the lowest bit of Weekdays
+    let sunday = if days_of_week & (1 | 1 << 7) != 0 {
+        1 << 6
+    } else {
+        0
+    };
+    let weekdays = Weekdays::from_bits(((days_of_week >> 1) & 0b11_1111) as u8 | sunday)
+        .ok_or_else(|| ScheduleError::InvalidCronField(fields[4].to_string()))?;
+
+    let times = (0..24)
+        .filter(|hour| hours & (1 << hour) != 0)
+        .cartesian_product((0..60).filter(|minute| minutes & (1 << minute) != 0))
+        .map(|(hour, minute)| NaiveTime::from_hms(hour, minute, 0))
+        .collect();
+
+    let mut schedule = Schedule::new(times, days_of_month, weekdays);
+    schedule.months = months;
+    Ok(schedule)
+}
+
+/// Parses a cron field into a bit mask, e.g. `1-5`, `*/15` or `0,30`.
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Result<u64, ScheduleError> {
+    let invalid = || ScheduleError::InvalidCronField(field.to_string());
+    let parse_value = |value: &str| value.parse::<u32>().map_err(|_| invalid());
+
+    let mut bits = 0;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, parse_value(step)?),
+            None => (part, 1),
+        };
+
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (parse_value(start)?, parse_value(end)?),
+            // A step without a range, e.g. `5/10`, continues to the end
+            None if step > 1 => (parse_value(range)?, max),
+            None => (parse_value(range)?, parse_value(range)?),
+        };
+
+        if step == 0 || start < min || end > max || start > end {
+            return Err(invalid());
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+
+    Ok(bits)
+}
+
+static INTERVAL_REGEX: OnceCell<Regex> = OnceCell::new();
+static TIMES_REGEX: OnceCell<Regex> = OnceCell::new();
+static DAY_OF_MONTH_REGEX: OnceCell<Regex> = OnceCell::new();
+
+fn parse_natural(s: &str) -> Result<Schedule, ScheduleError> {
+    let interval_regex = INTERVAL_REGEX.get_or_init(|| {
+        Regex::new(r"^(?P<days>.*?)\s*every (?P<count>\d+) (?P<unit>hours?|minutes?)(?: between (?P<start>\d{1,2}) and (?P<end>\d{1,2}))?$")
+            .expect("Failed to compile interval regex")
+    });
+    let times_regex = TIMES_REGEX.get_or_init(|| {
+        Regex::new(r"^(?P<before>.*?)\s*(?:\bat\s+)?(?P<times>\d{1,2}:\d{2}(?:\s*,\s*\d{1,2}:\d{2})*)(?P<after>.*)$")
+            .expect("Failed to compile times regex")
+    });
+
+    let unrecognized = || ScheduleError::Unrecognized(s.to_string());
+
+    let (days, times) = if let Some(captures) = interval_regex.captures(s) {
+        let count = captures["count"]
+            .parse::<u32>()
+            .map_err(|_| unrecognized())?;
+        let step = if captures["unit"].starts_with("hour") {
+            count.checked_mul(60)
+        } else {
+            Some(count)
+        };
+        let step = step.filter(|step| *step > 0).ok_or_else(unrecognized)?;
+        let start = captures
+            .name("start")
+            .map_or(Ok(0), |start| start.as_str().parse::<u32>())
+            .map_err(|_| unrecognized())?;
+        let end = captures
+            .name("end")
+            .map_or(Ok(23 * 60 + 59), |end| {
+                end.as_str().parse::<u32>().map(|hour| hour * 60)
+            })
+            .map_err(|_| unrecognized())?;
+
+        if start > 23 || end >= 24 * 60 || start * 60 > end {
+            return Err(unrecognized());
+        }
+
+        let times = (start * 60..=end)
+            .step_by(step as usize)
+            .map(|minute| NaiveTime::from_hms(minute / 60, minute % 60, 0))
+            .collect();
+
+        (
+            captures.name("days").map_or("", |days| days.as_str()),
+            times,
+        )
+    } else if let Some(captures) = times_regex.captures(s) {
+        let before = captures
+            .name("before")
+            .map_or("", |before| before.as_str().trim());
+        let after = captures
+            .name("after")
+            .map_or("", |after| after.as_str().trim());
+
+        // The days can be given either before or after the times, e.g. "08:00 ma-pe"
+        let days = match (before, after) {
+            (days, "") | ("", days) => days,
+            _ => return Err(unrecognized()),
+        };
+
+        let times = captures["times"]
+            .split(',')
+            .map(|time| {
+                NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                    .map_err(|_| ScheduleError::InvalidTime(time.trim().to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        (days, times)
+    } else {
+        return Err(unrecognized());
+    };
+
+    let (days_of_month, weekdays) = parse_days(days).ok_or_else(unrecognized)?;
+
+    Ok(Schedule::new(times, days_of_month, weekdays))
+}
+
+fn parse_days(s: &str) -> Option<(u32, Weekdays)> {
+    let day_of_month_regex = DAY_OF_MONTH_REGEX.get_or_init(|| {
+        Regex::new(r"^(\d{1,2})(?:st|nd|rd|th|\.)?(?: day)? of (?:the |every )?month$")
+            .expect("Failed to compile day of month regex")
+    });
+
+    let s = s.trim();
+    let s = s.strip_prefix("every ").unwrap_or(s);
+    let s = s.strip_prefix("on ").unwrap_or(s).trim();
+
+    if matches!(s, "" | "day") {
+        return Some((ALL_DAYS_OF_MONTH, Weekdays::ALL));
+    }
+
+    if let Some(captures) = day_of_month_regex.captures(s) {
+        let day = captures[1].parse::<u32>().ok()?;
+        return match day {
+            1..=31 => Some((1 << day, Weekdays::ALL)),
+            _ => None,
+        };
+    }
+
+    Weekdays::from_str(s)
+        .ok()
+        .map(|weekdays| (ALL_DAYS_OF_MONTH, weekdays))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date_time(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(year, month, day).and_hms(hour, minute, 0)
+    }
+
+    fn next_after(expression: &str, after: NaiveDateTime) -> NaiveDateTime {
+        Schedule::from_str(expression)
+            .unwrap()
+            .next_after(after)
+            .unwrap()
+    }
+
+    #[test]
+    fn parse_named_weekdays() {
+        assert_eq!(Weekdays::from_str("arkisin").unwrap(), Weekdays::WORKDAYS);
+        assert_eq!(Weekdays::from_str("Weekends").unwrap(), Weekdays::WEEKEND);
+    }
+
+    #[test]
+    fn parse_weekday_list_and_ranges() {
+        let weekdays = Weekdays::from_str("ma,ke-pe").unwrap();
+        assert_eq!(weekdays.to_string(), "ma,ke,to,pe");
+
+        let weekdays = Weekdays::from_str("sat-mon").unwrap();
+        assert_eq!(weekdays.to_string(), "ma,la,su");
+
+        assert_eq!(Weekdays::from_str("ma-su").unwrap(), Weekdays::ALL);
+    }
+
+    #[test]
+    fn reject_invalid_weekdays() {
+        assert!(Weekdays::from_str("").is_err());
+        assert!(Weekdays::from_str("ma,xx").is_err());
+        assert!(Weekdays::from_bits(0b1000_0000).is_none());
+    }
+
+    #[test]
+    fn daily_time() {
+        // 2022-09-24 is a Saturday
+        let after = date_time(2022, 9, 24, 9, 0);
+        assert_eq!(next_after("08:00", after), date_time(2022, 9, 25, 8, 0));
+        assert_eq!(
+            next_after("8:00, 12:30", after),
+            date_time(2022, 9, 24, 12, 30)
+        );
+    }
+
+    #[test]
+    fn weekdays_before_or_after_time() {
+        let after = date_time(2022, 9, 24, 9, 0);
+        assert_eq!(
+            next_after("every monday 08:00", after),
+            date_time(2022, 9, 26, 8, 0)
+        );
+        assert_eq!(
+            next_after("08:00 ma-pe", after),
+            date_time(2022, 9, 26, 8, 0)
+        );
+        assert_eq!(
+            next_after("weekends at 10:00", after),
+            date_time(2022, 9, 24, 10, 0)
+        );
+    }
+
+    #[test]
+    fn day_of_month() {
+        let after = date_time(2022, 9, 24, 9, 0);
+        assert_eq!(
+            next_after("1st of month 12:00", after),
+            date_time(2022, 10, 1, 12, 0)
+        );
+        assert_eq!(
+            next_after("31st of month 12:00", after),
+            date_time(2022, 10, 31, 12, 0)
+        );
+    }
+
+    #[test]
+    fn interval() {
+        let schedule = Schedule::from_str("every 2 hours between 9 and 17").unwrap();
+        assert_eq!(
+            schedule.times,
+            [9, 11, 13, 15, 17].map(|hour| NaiveTime::from_hms(hour, 0, 0))
+        );
+
+        let after = date_time(2022, 9, 24, 9, 10);
+        assert_eq!(
+            next_after("every 30 minutes", after),
+            date_time(2022, 9, 24, 9, 30)
+        );
+    }
+
+    #[test]
+    fn cron() {
+        let after = date_time(2022, 9, 24, 9, 0);
+        assert_eq!(
+            next_after("0 8 * * 1-5", after),
+            date_time(2022, 9, 26, 8, 0)
+        );
+        assert_eq!(
+            next_after("*/15 * * * *", after),
+            date_time(2022, 9, 24, 9, 15)
+        );
+        assert_eq!(
+            next_after("0 12 29 2 *", after),
+            date_time(2024, 2, 29, 12, 0)
+        );
+        // Sunday can be either 0 or 7
+        assert_eq!(next_after("0 8 * * 7", after), date_time(2022, 9, 25, 8, 0));
+    }
+
+    #[test]
+    fn reject_invalid_schedules() {
+        assert!(matches!(
+            Schedule::from_str("whenever"),
+            Err(ScheduleError::Unrecognized(_))
+        ));
+        assert!(matches!(
+            Schedule::from_str("25:00"),
+            Err(ScheduleError::InvalidTime(_))
+        ));
+        assert!(matches!(
+            Schedule::from_str("0 8 * * 8"),
+            Err(ScheduleError::InvalidCronField(_))
+        ));
+        assert!(matches!(
+            Schedule::from_str("0 0 30 2 *"),
+            Err(ScheduleError::NeverFires)
+        ));
+        assert!(matches!(
+            Schedule::from_str("every 0 minutes"),
+            Err(ScheduleError::Unrecognized(_))
+        ));
+        assert!(matches!(
+            Schedule::from_str("every 4294967295 hours"),
+            Err(ScheduleError::Unrecognized(_))
+        ));
+        assert!(matches!(
+            Schedule::from_str("every 99999999999 minutes"),
+            Err(ScheduleError::Unrecognized(_))
+        ));
+    }
+}
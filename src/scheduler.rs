@@ -1,14 +1,33 @@
+use std::sync::Arc;
+
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use teloxide::prelude::*;
+use tokio::sync::mpsc;
 
 use crate::{
+    chat_config::ChatConfigModel,
+    comics::{ComicRegistry, ComicRequest},
+    command_handler::HandlerError,
     db::DatabaseRef,
-    handlers::{handle_fingerpori, handle_lasaga},
-    subscriptions::{Subscription, SubscriptionType, TIME_FORMAT},
+    google::GoogleCalendarClientFactory,
+    handlers::{get_subscription_comics, handle_calendar_digest, send_comic},
+    subscriptions::{
+        FailureAction, Subscription, SubscriptionAction, SubscriptionType, MAX_RETRIES,
+    },
 };
 
+/// Shared state the scheduled tasks need.
+struct SchedulerContext {
+    db: DatabaseRef,
+    chat_config_map: Arc<ChatConfigModel>,
+    google_calendar_client_factory: GoogleCalendarClientFactory,
+    comic_registry: Arc<ComicRegistry>,
+}
+
 async fn handle_scheduled_task(
     bot: &AutoSend<Bot>,
+    context: &SchedulerContext,
     subscription: Subscription,
 ) -> anyhow::Result<()> {
     log::info!(
@@ -19,46 +38,157 @@ async fn handle_scheduled_task(
 
     match subscription.kind {
         SubscriptionType::Comics => {
-            handle_fingerpori(bot, subscription.chat_id)
-                .await
-                .context("handle_fingerpori (scheduled)")?;
+            let comic_ids = get_subscription_comics(&context.db, subscription.chat_id).await?;
+            let today = context
+                .chat_config_map
+                .get(subscription.chat_id)
+                .await?
+                .today();
 
-            handle_lasaga(bot, subscription.chat_id)
+            let mut sent = 0;
+            let mut last_error = None;
+
+            for id in comic_ids {
+                // Comics that are no longer available are skipped rather than failing the whole delivery
+                let source = match context.comic_registry.get(&id) {
+                    Some(source) => source,
+                    None => {
+                        log::warn!(
+                            "Unknown comic {} selected in chat {:?}",
+                            id,
+                            subscription.chat_id
+                        );
+                        continue;
+                    }
+                };
+
+                let result = send_comic(
+                    bot,
+                    &context.db,
+                    subscription.chat_id,
+                    &context.comic_registry,
+                    source,
+                    ComicRequest::Latest,
+                    today,
+                )
                 .await
-                .context("handle_lasaga (scheduled)")?;
+                .with_context(|| format!("send_comic {} (scheduled)", id));
+
+                // Retrying would post the comics that were sent again, so one failing comic
+                // does not keep the others from being delivered
+                match result {
+                    Ok(()) => sent += 1,
+                    Err(err) => {
+                        log::error!(
+                            "Failed to send scheduled comic for chat {:?}: {:#}",
+                            subscription.chat_id,
+                            err
+                        );
+                        last_error = Some(err);
+                    }
+                }
+            }
+
+            // The run is retried only if nothing was delivered
+            match last_error {
+                Some(err) if sent == 0 => Err(err),
+                _ => Ok(()),
+            }
+        }
+        SubscriptionType::Events => {
+            let result = handle_calendar_digest(
+                bot,
+                subscription.chat_id,
+                &context.db,
+                &context.chat_config_map,
+                &context.google_calendar_client_factory,
+            )
+            .await;
+
+            match result {
+                // Let the chat know why the digest is missing
+                Err(HandlerError::ErrorReply(reply)) => {
+                    bot.send_message(subscription.chat_id, reply).await?;
+                }
+                result => {
+                    result.context("handle_calendar_digest (scheduled)")?;
+                }
+            }
 
             Ok(())
         }
-        SubscriptionType::Events => Ok(()),
     }
 }
 
-const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+/// Upper limit for sleeping between checks, so that e.g. system clock changes are noticed eventually.
+const MAX_SLEEP: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// How soon to try again after reading the subscriptions failed.
+const ERROR_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Wakes up the scheduler so that it recomputes when the next subscription is due.
+/// Needed whenever subscriptions or chat timezones change.
+#[derive(Clone)]
+pub struct SchedulerWakeup(mpsc::UnboundedSender<()>);
+
+impl SchedulerWakeup {
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<()>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (Self(sender), receiver)
+    }
+
+    pub fn wake(&self) {
+        // The scheduler is only gone when shutting down
+        let _ = self.0.send(());
+    }
+}
+
+pub async fn scheduled_event_handler(
+    bot: AutoSend<Bot>,
+    db: DatabaseRef,
+    chat_config_map: Arc<ChatConfigModel>,
+    google_calendar_client_factory: GoogleCalendarClientFactory,
+    comic_registry: Arc<ComicRegistry>,
+    mut receive_wakeup: mpsc::UnboundedReceiver<()>,
+) -> anyhow::Result<()> {
+    let context = SchedulerContext {
+        db,
+        chat_config_map,
+        google_calendar_client_factory,
+        comic_registry,
+    };
 
-pub async fn scheduled_event_handler(bot: AutoSend<Bot>, db: DatabaseRef) -> anyhow::Result<()> {
     let ctrl_c_signal = tokio::signal::ctrl_c();
     // This is technically a oneshot channel, but actual tokio oneshot channel cannot be be listened to in a loop.
-    let (send_shutdown, mut receive_shutdown) = tokio::sync::mpsc::unbounded_channel();
+    let (send_shutdown, mut receive_shutdown) = mpsc::unbounded_channel();
 
     let handler_task = tokio::spawn(async move {
-        let db = db.clone();
-
         loop {
-            match handle_subscriptions(&db, &bot).await {
-                Ok(()) => {}
+            let sleep_duration = match handle_subscriptions(&context, &bot).await {
+                Ok(Some(next_run)) => (next_run - Utc::now())
+                    .to_std()
+                    .unwrap_or(std::time::Duration::ZERO)
+                    .min(MAX_SLEEP),
+                Ok(None) => MAX_SLEEP,
                 Err(err) => {
                     log::error!("Error while handling scheduled event{:#}", err);
+                    ERROR_RETRY_INTERVAL
                 }
-            }
+            };
 
-            // Wait for the next poll interval
+            log::debug!("Next scheduled task check in {:?}", sleep_duration);
+
+            // Wait until the next subscription is due
+            // or
+            // wait for subscriptions to change
             // or
             // wait for the shutdown signal
             tokio::select! {
                 _ = receive_shutdown.recv() => {
                     break;
                 }
-                _ = tokio::time::sleep(POLL_INTERVAL) => { }
+                Some(()) = receive_wakeup.recv() => { }
+                _ = tokio::time::sleep(sleep_duration) => { }
             }
         }
 
@@ -81,41 +211,167 @@ pub async fn scheduled_event_handler(bot: AutoSend<Bot>, db: DatabaseRef) -> any
     Ok(())
 }
 
-const OUTDATED_SUBSCRIPTIONS_THRESHOLD_MINUTES: i64 = 60;
-
-async fn handle_subscriptions(db: &DatabaseRef, bot: &AutoSend<Bot>) -> Result<(), anyhow::Error> {
-    let now = chrono::Local::now();
-    let subscriptions = db
-        .get_pending_subscriptions(now)
+/// Handles the subscriptions that are due and returns when the next one will be.
+async fn handle_subscriptions(
+    context: &SchedulerContext,
+    bot: &AutoSend<Bot>,
+) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+    let subscriptions = context
+        .db
+        .get_subscriptions()
         .await
-        .context("Failed to read pending subscriptions")?;
+        .context("Failed to read subscriptions")?;
+
+    let mut next_due: Option<DateTime<Utc>> = None;
 
     for subscription in subscriptions {
-        // If the scheduled time was under 15 minutes ago, handle it.
-        if (now.time() - subscription.time)
-            < chrono::Duration::minutes(OUTDATED_SUBSCRIPTIONS_THRESHOLD_MINUTES)
-        {
-            handle_scheduled_task(bot, subscription.clone())
-                .await
-                .context("Failed to handle scheduled task")?;
-            log::info!(
-                "Handled scheduled task {} for chat {:?}",
+        let id = subscription.id;
+
+        // A failing subscription must not keep the others from being handled
+        match handle_subscription(context, bot, subscription).await {
+            Ok(Some(next_run)) => {
+                next_due = Some(next_due.map_or(next_run, |next_due| next_due.min(next_run)));
+            }
+            Ok(None) => {}
+            Err(err) => {
+                log::error!("Error while handling subscription {}: {:#}", id, err);
+            }
+        }
+    }
+
+    Ok(next_due)
+}
+
+/// Handles the subscription if it is due and returns when it should be handled next.
+async fn handle_subscription(
+    context: &SchedulerContext,
+    bot: &AutoSend<Bot>,
+    mut subscription: Subscription,
+) -> anyhow::Result<Option<DateTime<Utc>>> {
+    // Subscription times are in the chat's own timezone
+    let chat_config = context.chat_config_map.get(subscription.chat_id).await?;
+    let now = chat_config.now().naive_local();
+
+    let scheduled_time = match subscription.action(now) {
+        SubscriptionAction::Wait(next_run) => {
+            return Ok(next_run.map(|next_run| chat_config.local_to_utc(next_run)));
+        }
+        SubscriptionAction::Run { scheduled_time } => scheduled_time,
+        SubscriptionAction::Skip { scheduled_time } => {
+            log::warn!(
+                "Skipping scheduled task {} {} for chat {:?} (scheduled time was {})",
+                subscription.id,
                 subscription.kind.as_str(),
-                subscription.chat_id
+                subscription.chat_id,
+                scheduled_time
             );
-        } else {
+
+            context
+                .db
+                .mark_subscription_updated(&subscription, now)
+                .await
+                .context("Failed to mark subscription as updated")?;
+
+            // A subscription that has never run has not missed anything the chat expected
+            if subscription.last_updated.is_some() {
+                send_notice(
+                    bot,
+                    &subscription,
+                    format!(
+                        "⏭️ Tilaus #{} {} jäi väliin, koska sen ajankohta {} oli jo yli {} minuuttia sitten.",
+                        subscription.id,
+                        subscription.kind.as_str(),
+                        chat_config.format_date_time(scheduled_time),
+                        subscription.catch_up_minutes
+                    ),
+                )
+                .await;
+            }
+
+            subscription.last_updated = Some(now);
+            return Ok(subscription
+                .next_run(now)
+                .map(|next_run| chat_config.local_to_utc(next_run)));
+        }
+    };
+
+    match handle_scheduled_task(bot, context, subscription.clone()).await {
+        Ok(()) => {
             log::info!(
-                "Skipping scheduled task {} for chat {:?} (scheduled time was {})",
+                "Handled scheduled task {} {} for chat {:?} (scheduled time was {})",
+                subscription.id,
                 subscription.kind.as_str(),
                 subscription.chat_id,
-                subscription.time.format(TIME_FORMAT)
+                scheduled_time
             );
         }
+        Err(err) => match subscription.after_failure(now) {
+            FailureAction::Retry {
+                retry_count,
+                retry_at,
+            } => {
+                log::warn!(
+                    "Scheduled task {} {} for chat {:?} failed, retry {}/{} at {}: {:#}",
+                    subscription.id,
+                    subscription.kind.as_str(),
+                    subscription.chat_id,
+                    retry_count,
+                    MAX_RETRIES,
+                    retry_at,
+                    err
+                );
+
+                context
+                    .db
+                    .mark_subscription_failed(&subscription, retry_count, retry_at)
+                    .await
+                    .context("Failed to mark subscription as failed")?;
+
+                return Ok(Some(chat_config.local_to_utc(retry_at)));
+            }
+            FailureAction::GiveUp => {
+                log::error!(
+                    "Scheduled task {} {} for chat {:?} failed after {} retries: {:#}",
+                    subscription.id,
+                    subscription.kind.as_str(),
+                    subscription.chat_id,
+                    MAX_RETRIES,
+                    err
+                );
 
-        db.mark_subscription_updated(&subscription, now)
-            .await
-            .context("Failed to mark subscription as updated")?;
+                send_notice(
+                    bot,
+                    &subscription,
+                    format!(
+                        "❌ Tilausta #{} {} ei saatu toimitettua {} yrityksen jälkeen. Yritetään taas seuraavalla kerralla.",
+                        subscription.id,
+                        subscription.kind.as_str(),
+                        MAX_RETRIES + 1
+                    ),
+                )
+                .await;
+            }
+        },
     }
 
-    Ok(())
+    context
+        .db
+        .mark_subscription_updated(&subscription, now)
+        .await
+        .context("Failed to mark subscription as updated")?;
+
+    subscription.last_updated = Some(now);
+    Ok(subscription
+        .next_run(now)
+        .map(|next_run| chat_config.local_to_utc(next_run)))
+}
+
+async fn send_notice(bot: &AutoSend<Bot>, subscription: &Subscription, notice: String) {
+    if let Err(err) = bot.send_message(subscription.chat_id, notice).await {
+        log::error!(
+            "Failed to send subscription notice to chat {:?}: {}",
+            subscription.chat_id,
+            err
+        );
+    }
 }
@@ -0,0 +1,3 @@
+ALTER TABLE autoreplies ADD COLUMN chance REAL;
+ALTER TABLE autoreplies ADD COLUMN cooldown_seconds INTEGER;
+ALTER TABLE autoreplies ADD COLUMN enabled INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,3 @@
+-- response_json now holds a list of weighted responses instead of a single response
+UPDATE autoreplies
+SET response_json = json_array(json_object('response', json(response_json), 'weight', 1));
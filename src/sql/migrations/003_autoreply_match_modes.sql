@@ -0,0 +1 @@
+ALTER TABLE autoreplies ADD COLUMN match_mode TEXT NOT NULL DEFAULT 'regex';
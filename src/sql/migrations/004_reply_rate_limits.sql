@@ -0,0 +1,2 @@
+ALTER TABLE chat_settings ADD COLUMN reply_burst INTEGER NOT NULL DEFAULT 5;
+ALTER TABLE chat_settings ADD COLUMN replies_per_hour INTEGER NOT NULL DEFAULT 60;
@@ -0,0 +1 @@
+ALTER TABLE chat_settings ADD COLUMN quiet_hours TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,2 @@
+ALTER TABLE chat_settings ADD COLUMN timezone TEXT NOT NULL DEFAULT 'Europe/Helsinki';
+ALTER TABLE chat_settings ADD COLUMN locale TEXT NOT NULL DEFAULT 'fi';
@@ -0,0 +1,17 @@
+-- Subscriptions get their own ids so that a chat can have several schedules of the same kind.
+-- The schedule is stored as an expression, e.g. "08:00" or "08:00 ma-pe", so existing times carry over as is.
+CREATE TABLE subscriptions_new (
+  id INTEGER NOT NULL PRIMARY KEY,
+  subscription_type TEXT NOT NULL REFERENCES subscription_types(id),
+  chat_id INTEGER NOT NULL,
+  schedule TEXT NOT NULL,
+  last_updated TEXT
+);
+
+INSERT INTO subscriptions_new (subscription_type, chat_id, schedule, last_updated)
+SELECT subscription_type, chat_id, time, last_updated FROM subscriptions;
+
+DROP TABLE subscriptions;
+ALTER TABLE subscriptions_new RENAME TO subscriptions;
+
+CREATE INDEX subscriptions_chat_id ON subscriptions (chat_id);
@@ -0,0 +1,3 @@
+ALTER TABLE subscriptions ADD COLUMN catch_up_minutes INTEGER NOT NULL DEFAULT 60;
+ALTER TABLE subscriptions ADD COLUMN retry_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE subscriptions ADD COLUMN retry_at TEXT;
@@ -0,0 +1,8 @@
+-- Comics posted by the chat's comics subscriptions, in order. Chats without rows get the defaults.
+CREATE TABLE chat_comics (
+  chat_id INTEGER NOT NULL,
+  comic_id TEXT NOT NULL,
+  position INTEGER NOT NULL,
+
+  PRIMARY KEY (chat_id, comic_id)
+);
@@ -0,0 +1,20 @@
+-- Resolved comic strips, so that the same strip is not scraped or uploaded again.
+CREATE TABLE comic_cache (
+  comic_id TEXT NOT NULL,
+  date TEXT NOT NULL,
+  image_url TEXT NOT NULL,
+  -- Telegram file_id of the uploaded image, known after the first send
+  file_id TEXT,
+
+  PRIMARY KEY (comic_id, date)
+);
+
+-- Strips shown in each chat, so that random picks can avoid recent repeats.
+CREATE TABLE comic_history (
+  chat_id INTEGER NOT NULL,
+  comic_id TEXT NOT NULL,
+  image_url TEXT NOT NULL,
+  shown_at TEXT NOT NULL,
+
+  PRIMARY KEY (chat_id, comic_id, image_url)
+);
@@ -0,0 +1,2 @@
+-- Serves the recent-repeat lookup and pruning of old history.
+CREATE INDEX comic_history_shown_at ON comic_history (chat_id, comic_id, shown_at);
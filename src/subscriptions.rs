@@ -1,10 +1,25 @@
 use std::str::FromStr;
 
-use chrono::NaiveTime;
+use chrono::{Duration, NaiveDateTime};
 use teloxide::types::ChatId;
 
+use crate::schedule::Schedule;
+
 pub const TIME_FORMAT: &str = "%H:%M";
 
+/// How late a scheduled run can still be delivered, unless configured otherwise.
+pub const DEFAULT_CATCH_UP_MINUTES: u32 = 60;
+
+/// Failed runs are retried this many times before giving up until the next scheduled run.
+pub const MAX_RETRIES: u32 = 5;
+
+/// Delay before the first retry, doubled for each following one.
+const RETRY_BASE_DELAY_MINUTES: i64 = 1;
+
+fn retry_delay(retry_count: u32) -> Duration {
+    Duration::minutes(RETRY_BASE_DELAY_MINUTES << (retry_count - 1))
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum SubscriptionType {
     Comics,
@@ -34,7 +49,257 @@ impl SubscriptionType {
 
 #[derive(Clone, Debug)]
 pub struct Subscription {
+    pub id: i64,
     pub chat_id: ChatId,
     pub kind: SubscriptionType,
-    pub time: NaiveTime,
+    /// Schedule in the chat's timezone.
+    pub schedule: Schedule,
+    /// When the subscription was last handled, in the chat's timezone.
+    pub last_updated: Option<NaiveDateTime>,
+    /// Runs that are more than this late are skipped, e.g. after the bot has been down.
+    pub catch_up_minutes: u32,
+    /// How many times the current run has failed.
+    pub retry_count: u32,
+    /// When to try a failed run again, in the chat's timezone.
+    pub retry_at: Option<NaiveDateTime>,
+}
+
+impl Subscription {
+    /// The next time the subscription should be handled, in the chat's timezone.
+    /// This can be in the past if the subscription is overdue.
+    pub fn next_run(&self, local_now: NaiveDateTime) -> Option<NaiveDateTime> {
+        // New subscriptions are marked handled when added, so this only applies to ones added
+        // before that. They are due from the start of the day.
+        let since = self
+            .last_updated
+            .unwrap_or_else(|| local_now.date().and_hms(0, 0, 0) - chrono::Duration::seconds(1));
+
+        self.schedule.next_after(since)
+    }
+
+    /// Decides what to do with the subscription at `now`, in the chat's timezone.
+    pub fn action(&self, now: NaiveDateTime) -> SubscriptionAction {
+        let next_run = match self.next_run(now) {
+            Some(next_run) if next_run <= now => next_run,
+            next_run => return SubscriptionAction::Wait(next_run),
+        };
+
+        // Only the latest of the runs missed e.g. while the bot was down is delivered
+        let scheduled_time = self
+            .schedule
+            .last_at_or_before(now)
+            .map_or(next_run, |latest| latest.max(next_run));
+
+        if let Some(retry_at) = self.retry_at {
+            if retry_at > now {
+                return SubscriptionAction::Wait(Some(retry_at));
+            }
+        }
+
+        if now - scheduled_time > Duration::minutes(self.catch_up_minutes.into()) {
+            SubscriptionAction::Skip { scheduled_time }
+        } else {
+            SubscriptionAction::Run { scheduled_time }
+        }
+    }
+
+    /// What to do after the current run has failed at `now`.
+    pub fn after_failure(&self, now: NaiveDateTime) -> FailureAction {
+        if self.retry_count < MAX_RETRIES {
+            let retry_count = self.retry_count + 1;
+
+            FailureAction::Retry {
+                retry_count,
+                retry_at: now + retry_delay(retry_count),
+            }
+        } else {
+            FailureAction::GiveUp
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SubscriptionAction {
+    /// Nothing is due until the given time, if ever.
+    Wait(Option<NaiveDateTime>),
+    Run {
+        scheduled_time: NaiveDateTime,
+    },
+    /// The run is later than the catch-up window allows.
+    Skip {
+        scheduled_time: NaiveDateTime,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FailureAction {
+    Retry {
+        retry_count: u32,
+        retry_at: NaiveDateTime,
+    },
+    /// The retries are exhausted, so the run is given up until the next scheduled one.
+    GiveUp,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::NaiveDate;
+
+    #[test]
+    fn next_run_on_scheduled_days() {
+        let subscription = Subscription {
+            id: 1,
+            chat_id: ChatId(1),
+            kind: SubscriptionType::Comics,
+            schedule: Schedule::from_str("weekends 08:00").unwrap(),
+            last_updated: None,
+            catch_up_minutes: DEFAULT_CATCH_UP_MINUTES,
+            retry_count: 0,
+            retry_at: None,
+        };
+
+        // 2022-09-24 is a Saturday
+        let saturday = NaiveDate::from_ymd(2022, 9, 24);
+        let now = saturday.and_hms(9, 0, 0);
+        assert_eq!(subscription.next_run(now), Some(saturday.and_hms(8, 0, 0)));
+
+        let handled = Subscription {
+            last_updated: Some(now),
+            ..subscription
+        };
+        assert_eq!(
+            handled.next_run(now),
+            Some(saturday.succ().and_hms(8, 0, 0))
+        );
+    }
+
+    #[test]
+    fn next_run_after_subscribing_past_todays_slot() {
+        let saturday = NaiveDate::from_ymd(2022, 9, 24);
+        let subscribed_at = saturday.and_hms(12, 0, 0);
+
+        let subscription = Subscription {
+            id: 1,
+            chat_id: ChatId(1),
+            kind: SubscriptionType::Comics,
+            schedule: Schedule::from_str("08:00").unwrap(),
+            last_updated: Some(subscribed_at),
+            catch_up_minutes: DEFAULT_CATCH_UP_MINUTES,
+            retry_count: 0,
+            retry_at: None,
+        };
+
+        // Today's slot has passed, so the first run is tomorrow instead of an overdue one today
+        let now = saturday.and_hms(12, 1, 0);
+        assert_eq!(
+            subscription.next_run(now),
+            Some(saturday.succ().and_hms(8, 0, 0))
+        );
+    }
+
+    fn subscription(schedule: &str, last_updated: NaiveDateTime) -> Subscription {
+        Subscription {
+            id: 1,
+            chat_id: ChatId(1),
+            kind: SubscriptionType::Comics,
+            schedule: Schedule::from_str(schedule).unwrap(),
+            last_updated: Some(last_updated),
+            catch_up_minutes: DEFAULT_CATCH_UP_MINUTES,
+            retry_count: 0,
+            retry_at: None,
+        }
+    }
+
+    #[test]
+    fn run_within_catch_up_window() {
+        let saturday = NaiveDate::from_ymd(2022, 9, 24);
+        let subscription = subscription("08:00", saturday.pred().and_hms(8, 0, 0));
+
+        assert_eq!(
+            subscription.action(saturday.and_hms(7, 59, 0)),
+            SubscriptionAction::Wait(Some(saturday.and_hms(8, 0, 0)))
+        );
+        assert_eq!(
+            subscription.action(saturday.and_hms(8, 30, 0)),
+            SubscriptionAction::Run {
+                scheduled_time: saturday.and_hms(8, 0, 0)
+            }
+        );
+        assert_eq!(
+            subscription.action(saturday.and_hms(9, 30, 0)),
+            SubscriptionAction::Skip {
+                scheduled_time: saturday.and_hms(8, 0, 0)
+            }
+        );
+    }
+
+    #[test]
+    fn run_latest_missed_interval() {
+        // The bot was down from 9:00 to 12:10, so the runs from 9:30 to 11:30 were missed
+        let saturday = NaiveDate::from_ymd(2022, 9, 24);
+        let subscription = subscription("every 30 minutes", saturday.and_hms(9, 0, 0));
+
+        assert_eq!(
+            subscription.action(saturday.and_hms(12, 10, 0)),
+            SubscriptionAction::Run {
+                scheduled_time: saturday.and_hms(12, 0, 0)
+            }
+        );
+    }
+
+    #[test]
+    fn skip_retries_outside_catch_up_window() {
+        let saturday = NaiveDate::from_ymd(2022, 9, 24);
+        let failing = Subscription {
+            retry_count: 2,
+            retry_at: Some(saturday.and_hms(8, 3, 0)),
+            ..subscription("08:00", saturday.pred().and_hms(8, 0, 0))
+        };
+
+        assert_eq!(
+            failing.action(saturday.and_hms(8, 2, 0)),
+            SubscriptionAction::Wait(Some(saturday.and_hms(8, 3, 0)))
+        );
+        assert_eq!(
+            failing.action(saturday.and_hms(8, 3, 0)),
+            SubscriptionAction::Run {
+                scheduled_time: saturday.and_hms(8, 0, 0)
+            }
+        );
+        // e.g. the bot was down when the retry was due
+        assert_eq!(
+            failing.action(saturday.and_hms(20, 0, 0)),
+            SubscriptionAction::Skip {
+                scheduled_time: saturday.and_hms(8, 0, 0)
+            }
+        );
+    }
+
+    #[test]
+    fn back_off_until_retries_are_exhausted() {
+        let now = NaiveDate::from_ymd(2022, 9, 24).and_hms(8, 0, 0);
+        let mut subscription = subscription("08:00", now);
+
+        let delays = (0..MAX_RETRIES)
+            .map(|retry| {
+                subscription.retry_count = retry;
+                match subscription.after_failure(now) {
+                    FailureAction::Retry {
+                        retry_count,
+                        retry_at,
+                    } => {
+                        assert_eq!(retry_count, retry + 1);
+                        (retry_at - now).num_minutes()
+                    }
+                    FailureAction::GiveUp => panic!("Gave up after {} retries", retry),
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [1, 2, 4, 8, 16]);
+
+        subscription.retry_count = MAX_RETRIES;
+        assert_eq!(subscription.after_failure(now), FailureAction::GiveUp);
+    }
 }
@@ -1,3 +1,8 @@
+use std::io::Cursor;
+
+use anyhow::Context;
+use image::{imageops::FilterType, ImageOutputFormat};
+
 const CHARACTERS_TO_ESCAPE: [char; 18] = [
     '_', '*', '[', ']', '(', ')', '~', '`', '>', '#', '+', '-', '=', '|', '{', '}', '.', '!',
 ];
@@ -14,3 +19,36 @@ pub fn telegram_escape(text: &str) -> String {
 
     escaped
 }
+
+/// Re-encodes an image, e.g. to send a WebP sticker as a photo.
+/// If `max_dimension_sum` is given, the image is first scaled down so that its width and height
+/// add up to at most that, as Telegram requires for photos.
+pub fn re_encode_image(
+    image_buffer: &[u8],
+    format: ImageOutputFormat,
+    max_dimension_sum: Option<u32>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut image = image::load_from_memory(image_buffer).context("Failed to load image :(")?;
+
+    let (width, height) = (image.width(), image.height());
+    if let Some(max_dimension_sum) = max_dimension_sum {
+        if width + height > max_dimension_sum {
+            let scale = f64::from(max_dimension_sum) / f64::from(width + height);
+            image = image.resize(
+                (f64::from(width) * scale) as u32,
+                (f64::from(height) * scale) as u32,
+                FilterType::Lanczos3,
+            );
+        }
+    }
+
+    // JPEG has no alpha channel
+    if matches!(format, ImageOutputFormat::Jpeg(_)) {
+        image = image.to_rgb8().into();
+    }
+
+    let mut encoded = Vec::new();
+    let mut encoded_cursor = Cursor::new(&mut encoded);
+    image.write_to(&mut encoded_cursor, format)?;
+    Ok(encoded)
+}